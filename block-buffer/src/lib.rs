@@ -25,11 +25,21 @@ impl<T: Block> BlockBuffer<T> {
         Self { buff, filled: 0 }
     }
 
-    /// Reads bytes writing them to the buffer and fills remaining bytes with zeroes
+    /// Reads bytes writing them to the buffer and fills remaining bytes with zeroes.
+    /// Reads until the buffer is full or the input ends, so a short read of a pipe
+    /// isn't taken for the end of the data
     pub fn read_bytes_from<R: std::io::Read>(&mut self, read: &mut R) -> std::io::Result<usize> {
         let bytes = unsafe { self.buff.align_to_mut().1 };
 
-        let len = read.read(bytes)?;
+        let mut len = 0;
+        while len < bytes.len() {
+            match read.read(&mut bytes[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
 
         let bs = Self::BLOCK_SIZE;
 
@@ -46,6 +56,10 @@ impl<T: Block> BlockBuffer<T> {
         unsafe { self.as_blocks().align_to().1 }
     }
 
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { self.as_blocks_mut().align_to_mut().1 }
+    }
+
     pub fn as_blocks(&self) -> &[T] {
         &self.buff[..self.filled]
     }
@@ -0,0 +1,77 @@
+//! Compares the table-driven round function with the nibble-by-nibble one
+//! and the bitsliced rounds
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lab1::{simple_swap_blocks_with, simple_swap_with, Backend, MagmaKey, Params, SBoxSet};
+
+const KEY: [u32; 8] = [
+    0xffeeddcc, 0xbbaa9988, 0x77665544, 0x33221100, 0xf0f1f2f3, 0xf4f5f6f7, 0xf8f9fafb, 0xfcfdfeff,
+];
+
+/// The round function as it was before the tables: two S-box lookups per byte and a rotation
+fn f_nibbles(input: u32, subkey: u32, tables: &[[u8; 16]; 8]) -> u32 {
+    let mut bytes = input.wrapping_add(subkey).to_le_bytes();
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte =
+            (tables[i * 2 + 1][(*byte >> 4) as usize] << 4) | tables[i * 2][(*byte & 0xf) as usize];
+    }
+    u32::from_le_bytes(bytes).rotate_left(11)
+}
+
+fn simple_swap_nibbles(input: u64, key: [u32; 8], tables: &[[u8; 16]; 8]) -> u64 {
+    let (mut n1, mut n2) = (input as u32, (input >> 32) as u32);
+    let schedule = key.iter().cycle().take(24).chain(key.iter().rev());
+
+    for &subkey in schedule {
+        (n1, n2) = (n2 ^ f_nibbles(n1, subkey, tables), n1);
+    }
+
+    ((n1 as u64) << 32) | n2 as u64
+}
+
+fn bench(c: &mut Criterion) {
+    let params = Params {
+        backend: Backend::Table,
+        ..Params::new(SBoxSet::CRYPTO_PRO_A)
+    };
+    let key = MagmaKey::new(KEY);
+    let blocks: Vec<u64> = (0..4096)
+        .map(|i: u64| i.wrapping_mul(0x9e3779b97f4a7c15))
+        .collect();
+
+    assert!(blocks
+        .iter()
+        .all(|&b| simple_swap_with(b, &key, &params)
+            == simple_swap_nibbles(b, KEY, &params.sbox.tables)));
+
+    let mut group = c.benchmark_group("simple_swap 32 KiB");
+    group.bench_function("tables", |b| {
+        b.iter(|| {
+            blocks.iter().fold(0, |acc, &block| {
+                acc ^ simple_swap_with(black_box(block), &key, &params)
+            })
+        })
+    });
+    group.bench_function("nibbles", |b| {
+        b.iter(|| {
+            blocks.iter().fold(0, |acc, &block| {
+                acc ^ simple_swap_nibbles(black_box(block), KEY, &params.sbox.tables)
+            })
+        })
+    });
+    group.bench_function("bitsliced", |b| {
+        let params = Params {
+            backend: Backend::Bitsliced,
+            ..params
+        };
+        b.iter(|| {
+            let mut blocks = black_box(blocks.clone());
+            simple_swap_blocks_with(&mut blocks, &key, &params);
+            blocks
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
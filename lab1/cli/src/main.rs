@@ -0,0 +1,1592 @@
+use std::{
+    io::*,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use block_buffer::{Block, BlockBuffer};
+use clap::{builder::PossibleValuesParser, builder::TypedValueParser, Parser, ValueEnum};
+use lab1::*;
+use zeroize::Zeroizing;
+
+const MAX_BUFF_SIZE: u64 = 1 << 30;
+const INIT_BLOCK: u64 = 0xBADF00D;
+const DEFAULT_KEY: [u32; 8] = [34, 42, 12, 53, 23, 23, 54, 34];
+const BLOCK_SIZE: usize = std::mem::size_of::<u64>();
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Mode {
+    /// Gamming with feedback
+    Cfb,
+    /// Counter mode: gamming of GOST 28147-89, or GOST R 34.13-2015 for magma and kuznyechik
+    Ctr,
+    /// Simple replacement, every block is encrypted on its own
+    Ecb,
+    /// Cipher block chaining of GOST R 34.13-2015
+    Cbc,
+    /// Output feedback of GOST R 34.13-2015
+    Ofb,
+    /// Counter mode of GOST R 34.13-2015 with ACPKM re-keying of RFC 8645
+    AcpkmCtr,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Cipher {
+    /// GOST 28147-89 with little-endian keys and blocks
+    #[default]
+    Gost28147,
+    /// GOST R 34.12-2015 Magma, as in RFC 8891
+    Magma,
+    /// GOST R 34.12-2015 Kuznyechik with 128-bit blocks, as in RFC 7801
+    Kuznyechik,
+}
+
+#[derive(Debug, clap::Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Commands {
+    /// Compute the imitovstavka of a file
+    Mac {
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        #[arg(
+            short = 'l',
+            long,
+            default_value_t = 4,
+            value_parser = clap::value_parser!(u8).range(1..=MAX_MAC_LEN as i64),
+            help = "MAC length in bytes"
+        )]
+        mac_len: u8,
+
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Check the imitovstavka of a file
+    VerifyMac {
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        #[arg(help = "Expected MAC in hex")]
+        mac: String,
+
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Wrap a 32-byte key with a gost28147 key encryption key, as in RFC 4357
+    WrapKey {
+        #[arg(value_name = "FILE", help = "Key file of the key to wrap")]
+        input: PathBuf,
+
+        #[arg(short, long)]
+        output: PathBuf,
+
+        #[arg(
+            long,
+            help = "Diversify the key encryption key with the UKM, CryptoPro key wrap"
+        )]
+        cryptopro: bool,
+
+        #[arg(long, value_name = "HEX", help = "UKM of 8 bytes [default: random]")]
+        ukm: Option<String>,
+
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Unwrap a key made by wrap-key into a raw key file
+    UnwrapKey {
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        #[arg(short, long)]
+        output: PathBuf,
+
+        #[arg(long, help = "The key was wrapped with the CryptoPro key wrap")]
+        cryptopro: bool,
+
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Report the DDT, LAT, differential uniformity, nonlinearity,
+    /// algebraic degree and fixed points of S-boxes
+    AnalyzeSbox {
+        #[arg(
+            long,
+            value_name = "NAME",
+            conflicts_with_all = ["kuznyechik", "candidate"],
+            help = "S-box parameter set, every table of it is analyzed [default: the default set]"
+        )]
+        sbox: Option<SBoxSet>,
+
+        #[arg(
+            long,
+            conflicts_with = "candidate",
+            help = "Analyze the Kuznyechik S-box"
+        )]
+        kuznyechik: bool,
+
+        #[arg(
+            long,
+            value_name = "VALUES",
+            value_delimiter = ',',
+            value_parser = parse_sbox_value,
+            help = "S-box to analyze as 2^n comma-separated outputs, decimal or 0x-prefixed hex"
+        )]
+        candidate: Option<Vec<u8>>,
+
+        #[arg(long, help = "Also print the DDT and the LAT")]
+        tables: bool,
+    },
+    /// Search for differential trails of gost28147 reduced to a few rounds
+    /// and count how often they hold under a random key
+    Trail {
+        #[arg(
+            long,
+            default_value_t = 3,
+            value_parser = clap::value_parser!(u8).range(1..=31),
+            help = "Rounds the trails cover"
+        )]
+        rounds: u8,
+
+        #[arg(long, value_name = "NAME", help = "S-box parameter set to use")]
+        sbox: Option<SBoxSet>,
+
+        #[arg(long, help = "Use the S-box layout of older lab1 versions")]
+        legacy: bool,
+
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 16,
+            value_parser = clap::value_parser!(u16).range(1..),
+            help = "Trails kept after every round of the search"
+        )]
+        width: u16,
+
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 1 << 16,
+            help = "Random pairs a trail is checked with"
+        )]
+        pairs: usize,
+
+        #[arg(
+            long,
+            help = "Recover the subkey of one more round under a random key with the trails"
+        )]
+        attack: bool,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct KeyArgs {
+    #[arg(short, long)]
+    key_file: Option<PathBuf>,
+
+    #[arg(short, long, value_enum, default_value_t)]
+    cipher: Cipher,
+
+    #[arg(long, value_name = "NAME", help = "S-box parameter set to use")]
+    sbox: Option<SBoxSet>,
+
+    #[arg(long, help = "Use the S-box layout of older lab1 versions")]
+    legacy: bool,
+
+    #[arg(
+        long,
+        default_value_t,
+        value_parser = PossibleValuesParser::new(Backend::ALL.map(Backend::name))
+            .map(|name| name.parse::<Backend>().unwrap()),
+        help = "How gost28147 and magma rounds are computed: auto is bitsliced for batches \
+                of blocks and uses tables for single ones, table is faster but not constant time"
+    )]
+    backend: Backend,
+
+    #[arg(
+        long,
+        conflicts_with = "key_file",
+        help = "Use the built-in key everyone knows instead of a passphrase, for tests only"
+    )]
+    insecure_default_key: bool,
+
+    /// Key derived from the passphrase with the salt of the header
+    #[arg(skip)]
+    derived: Option<Zeroizing<[u8; 32]>>,
+}
+
+/// GOST 28147-89 keys are little-endian words
+fn gost28147_key(bytes: &[u8; 32]) -> MagmaKey {
+    MagmaKey::new(std::array::from_fn(|i| {
+        u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap())
+    }))
+}
+
+impl KeyArgs {
+    /// Without a key file the key comes from a passphrase
+    fn uses_passphrase(&self) -> bool {
+        self.key_file.is_none() && !self.insecure_default_key
+    }
+
+    fn key_bytes(&self) -> Result<Zeroizing<[u8; 32]>> {
+        let Some(key_file_path) = &self.key_file else {
+            if let Some(key) = &self.derived {
+                return Ok(key.clone());
+            }
+
+            if self.insecure_default_key {
+                return Ok(Zeroizing::new(std::array::from_fn(|i| {
+                    DEFAULT_KEY[i / 4].to_le_bytes()[i % 4]
+                })));
+            }
+
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "no key: there is no header with a passphrase salt, \
+                 give --key-file or --insecure-default-key",
+            ));
+        };
+
+        let key_file = KeyFile::read(key_file_path)?;
+        let algorithm = self.cipher.to_possible_value().unwrap();
+        let key = &key_file.key;
+
+        match self.cipher {
+            Cipher::Gost28147 => {
+                let cipher = Gost28147::with_params(gost28147_key(key), self.params()?);
+                key_file.check(algorithm.get_name(), &cipher)
+            }
+            Cipher::Magma => key_file.check(algorithm.get_name(), &Magma::new(key)),
+            Cipher::Kuznyechik => key_file.check(algorithm.get_name(), &Kuznyechik::new(key)),
+        }?;
+
+        Ok(Zeroizing::new(key_file.key))
+    }
+
+    /// Check value of `key` with the cipher and the S-boxes of the arguments
+    fn check_value(&self, key: &[u8; 32]) -> Result<[u8; KCV_LEN]> {
+        Ok(match self.cipher {
+            Cipher::Gost28147 => {
+                key_check_value(&Gost28147::with_params(gost28147_key(key), self.params()?))
+            }
+            Cipher::Magma => key_check_value(&Magma::new(key)),
+            Cipher::Kuznyechik => key_check_value(&Kuznyechik::new(key)),
+        })
+    }
+
+    fn key(&self) -> Result<MagmaKey> {
+        let buff = self.key_bytes()?;
+
+        if self.cipher == Cipher::Magma {
+            return Ok(Magma::new(&buff).key().clone());
+        }
+
+        Ok(gost28147_key(&buff))
+    }
+
+    fn check_options(&self) -> Result<()> {
+        if self.cipher != Cipher::Gost28147 && (self.sbox.is_some() || self.legacy) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "S-boxes and layout can be chosen for gost28147 only",
+            ));
+        }
+
+        if self.cipher == Cipher::Kuznyechik && self.backend != Backend::Auto {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the backend can be chosen for gost28147 and magma only",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn params(&self) -> Result<Params> {
+        self.check_options()?;
+
+        match self.cipher {
+            Cipher::Gost28147 => {}
+            Cipher::Magma => {
+                return Ok(Params {
+                    backend: self.backend,
+                    ..Magma::PARAMS
+                })
+            }
+            Cipher::Kuznyechik => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "kuznyechik has no GOST 28147-89 parameters",
+                ))
+            }
+        }
+
+        Ok(Params {
+            sbox: self.sbox.unwrap_or_default(),
+            layout: if self.legacy {
+                Layout::Legacy
+            } else {
+                Layout::Standard
+            },
+            order: ByteOrder::Little,
+            backend: self.backend,
+        })
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct Args {
+    #[arg(short, long)]
+    decrypt: bool,
+
+    #[arg(short, long, value_enum, default_value_t = Mode::Cfb)]
+    mode: Mode,
+
+    #[arg(
+        long,
+        default_value_t,
+        value_parser = PossibleValuesParser::new(Padding::ALL.map(Padding::name))
+            .map(|name| name.parse::<Padding>().unwrap()),
+        help = "Padding of the last block in ecb and cbc modes"
+    )]
+    padding: Padding,
+
+    #[arg(
+        long,
+        value_parser = parse_register_bits,
+        help = "Register size of cfb, cbc and ofb modes, a multiple of the block size [default: block size]"
+    )]
+    register_bits: Option<usize>,
+
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(["8", "16", "32", "64", "128"]).map(|s| s.parse::<usize>().unwrap()),
+        help = "Bits fed back per step in cfb mode [default: block size]"
+    )]
+    segment_bits: Option<usize>,
+
+    #[arg(
+        long,
+        default_value_t = 4096,
+        value_name = "BYTES",
+        help = "Section size of acpkm-ctr mode, a multiple of the block size"
+    )]
+    section_size: usize,
+
+    #[arg(
+        long,
+        help = "CryptoPro key meshing of RFC 4357 every 1024 bytes, cfb mode only"
+    )]
+    key_meshing: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+        help = "Threads for ctr and ecb modes"
+    )]
+    threads: NonZeroUsize,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = lab5::KDF_ITERATIONS,
+        value_parser = clap::value_parser!(u32).range(1..=lab1::MAX_KDF_ITERATIONS as i64),
+        help = "Iterations of the key derivation from a passphrase"
+    )]
+    kdf_iterations: u32,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["mode", "padding", "register_bits", "segment_bits", "key_meshing"],
+        help = "Authenticated encryption in MGM, the tag is appended to the output"
+    )]
+    aead: bool,
+
+    #[arg(
+        long,
+        requires = "aead",
+        value_name = "HEX",
+        help = "MGM nonce of a block, the highest bit must be zero"
+    )]
+    nonce: Option<String>,
+
+    #[arg(
+        long,
+        requires = "aead",
+        value_name = "FILE",
+        help = "File with associated data to authenticate"
+    )]
+    aad: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Don't write or expect a header, the IV is the fixed one of older versions"
+    )]
+    no_header: bool,
+
+    #[arg(
+        long,
+        value_name = "OFFSET:LEN",
+        value_parser = parse_range,
+        requires = "decrypt",
+        conflicts_with = "aead",
+        help = "Decrypt only LEN bytes of the plaintext from OFFSET, ctr mode only"
+    )]
+    range: Option<ByteRange>,
+
+    #[arg(
+        value_name = "FILE",
+        required = true,
+        help = "File to read, - for stdin"
+    )]
+    input: Option<PathBuf>,
+
+    #[arg(short, long, required = true, help = "File to write, - for stdout")]
+    output: Option<PathBuf>,
+
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+/// A part of the plaintext, `len` bytes from `offset`
+#[derive(Debug, Clone, Copy)]
+struct ByteRange {
+    offset: u64,
+    len: u64,
+}
+
+fn parse_range(s: &str) -> std::result::Result<ByteRange, String> {
+    let (offset, len) = s.split_once(':').ok_or("expected OFFSET:LEN")?;
+
+    Ok(ByteRange {
+        offset: offset.parse().map_err(|e| format!("offset: {e}"))?,
+        len: len.parse().map_err(|e| format!("length: {e}"))?,
+    })
+}
+
+fn parse_register_bits(s: &str) -> std::result::Result<usize, String> {
+    let bits: usize = s.parse().map_err(|e| format!("{e}"))?;
+
+    if bits == 0 || !bits.is_multiple_of(64) {
+        return Err("must be a positive multiple of 64".into());
+    }
+
+    Ok(bits)
+}
+
+/// Runs `transform` on the numbers the blocks stand for in `order`
+fn in_order(order: ByteOrder, blocks: &mut [u64], transform: impl FnOnce(&mut [u64])) {
+    order.convert(blocks);
+    transform(blocks);
+    order.convert(blocks);
+}
+
+/// Register size in blocks and segment size in bytes for blocks of `block_size` bytes
+fn feedback_sizes(args: &Args, block_size: usize) -> Result<(usize, usize)> {
+    let block_bits = block_size * 8;
+    let register_bits = args.register_bits.unwrap_or(block_bits);
+    let segment_bits = args.segment_bits.unwrap_or(block_bits);
+
+    if register_bits == 0
+        || !register_bits.is_multiple_of(block_bits)
+        || !segment_bits.is_power_of_two()
+        || !(8..=block_bits).contains(&segment_bits)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("register must be a multiple of {block_bits} bits and segment must fit in it"),
+        ));
+    }
+
+    Ok((register_bits / block_bits, segment_bits / 8))
+}
+
+/// ACPKM section size in blocks of `block_size` bytes
+fn section_blocks(args: &Args, block_size: usize) -> Result<usize> {
+    if args.section_size == 0 || !args.section_size.is_multiple_of(block_size) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("section size must be a positive multiple of {block_size} bytes"),
+        ));
+    }
+
+    Ok(args.section_size / block_size)
+}
+
+fn block_size(cipher: Cipher) -> usize {
+    match cipher {
+        Cipher::Gost28147 | Cipher::Magma => BLOCK_SIZE,
+        Cipher::Kuznyechik => 16,
+    }
+}
+
+impl From<Cipher> for CipherId {
+    fn from(value: Cipher) -> Self {
+        match value {
+            Cipher::Gost28147 => Self::Gost28147,
+            Cipher::Magma => Self::Magma,
+            Cipher::Kuznyechik => Self::Kuznyechik,
+        }
+    }
+}
+
+impl From<Mode> for ModeId {
+    fn from(value: Mode) -> Self {
+        match value {
+            Mode::Cfb => Self::Cfb,
+            Mode::Ctr => Self::Ctr,
+            Mode::Ecb => Self::Ecb,
+            Mode::Cbc => Self::Cbc,
+            Mode::Ofb => Self::Ofb,
+            Mode::AcpkmCtr => Self::AcpkmCtr,
+        }
+    }
+}
+
+/// The IV of the chosen cipher and mode as the size of its big-endian numbers and their count
+fn iv_shape(args: &Args) -> Result<(usize, usize)> {
+    let block_size = block_size(args.key.cipher);
+
+    Ok(match args.mode {
+        _ if args.aead => (block_size, 1),
+        Mode::Cfb | Mode::Cbc | Mode::Ofb => (block_size, feedback_sizes(args, block_size)?.0),
+        Mode::Ctr if args.key.cipher == Cipher::Magma => (4, 1),
+        Mode::Ctr => (8, 1),
+        Mode::AcpkmCtr if block_size == BLOCK_SIZE => (4, 1),
+        Mode::AcpkmCtr => (8, 1),
+        Mode::Ecb => (0, 0),
+    })
+}
+
+fn given_nonce(args: &Args) -> Result<Option<Vec<u8>>> {
+    let Some(hex) = &args.nonce else {
+        return Ok(None);
+    };
+
+    let nonce = parse_hex(hex)?;
+
+    if nonce.len() != block_size(args.key.cipher) || nonce[0] & 0x80 != 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, "invalid nonce"));
+    }
+
+    Ok(Some(nonce))
+}
+
+/// The IV of files without a header: the nonce given or `INIT_BLOCK` in every number.
+/// MGM has no default nonce, a repeated one gives away the plaintext and the tags.
+fn legacy_iv(args: &Args) -> Result<Vec<u8>> {
+    if let Some(nonce) = given_nonce(args)? {
+        return Ok(nonce);
+    }
+
+    if args.aead {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--aead without a header needs a --nonce",
+        ));
+    }
+
+    let (size, count) = iv_shape(args)?;
+    let init = (INIT_BLOCK as u128).to_be_bytes();
+
+    Ok(init[init.len() - size..].repeat(count))
+}
+
+/// Reads the header when decrypting and takes the cipher, the mode and the register size from it.
+/// When encrypting makes a header with a random IV.
+fn header(args: &mut Args, input: &mut Input, len: Option<u64>) -> Result<Header> {
+    if !args.decrypt {
+        let (size, count) = iv_shape(args)?;
+        let mode = if args.aead {
+            ModeId::Mgm
+        } else {
+            args.mode.into()
+        };
+        let len = len.unwrap_or(UNKNOWN_LEN);
+        let mut header = Header::random(args.key.cipher.into(), mode, len, size * count);
+
+        if args.key.uses_passphrase() {
+            header.kdf = Some(KdfParams::random(args.kdf_iterations));
+        }
+
+        header.options = cipher_options(args)?;
+
+        if args.aead {
+            match given_nonce(args)? {
+                Some(nonce) => header.iv = nonce,
+                None => header.iv[0] &= 0x7f,
+            }
+        }
+
+        return Ok(header);
+    }
+
+    let header = Header::read_from(input)?;
+
+    args.key.cipher = match header.cipher {
+        CipherId::Gost28147 => Cipher::Gost28147,
+        CipherId::Magma => Cipher::Magma,
+        CipherId::Kuznyechik => Cipher::Kuznyechik,
+        CipherId::Belt => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "the file is encrypted with BelT by lab2",
+            ))
+        }
+    };
+
+    args.aead = header.mode == ModeId::Mgm;
+    args.mode = match header.mode {
+        ModeId::Cfb => Mode::Cfb,
+        ModeId::Ctr => Mode::Ctr,
+        ModeId::Ecb => Mode::Ecb,
+        ModeId::Cbc => Mode::Cbc,
+        ModeId::Ofb => Mode::Ofb,
+        ModeId::AcpkmCtr => Mode::AcpkmCtr,
+        ModeId::Mgm => args.mode,
+    };
+
+    if !args.aead && matches!(args.mode, Mode::Cfb | Mode::Cbc | Mode::Ofb) {
+        args.register_bits = Some(header.iv.len() * 8);
+    }
+
+    let options = header.options;
+
+    if args.key.cipher == Cipher::Gost28147 {
+        args.key.sbox = Some(options.sbox);
+        args.key.legacy = options.layout == Layout::Legacy;
+    }
+
+    args.segment_bits = options.segment.map(|segment| segment as usize * 8);
+    args.padding = options.padding;
+    args.key_meshing = options.key_meshing;
+
+    if !args.aead && matches!(args.mode, Mode::AcpkmCtr) {
+        args.section_size = options.section_size as usize;
+    }
+
+    let (size, count) = iv_shape(args)?;
+
+    if header.iv.len() != size * count {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "the IV in the header doesn't fit the mode",
+        ));
+    }
+
+    Ok(header)
+}
+
+/// Options of the cipher and the mode for the header, the ones
+/// the cipher and the mode don't have are left at their defaults
+fn cipher_options(args: &Args) -> Result<CipherOptions> {
+    let mut options = CipherOptions::default();
+
+    if args.key.cipher == Cipher::Gost28147 {
+        let params = args.key.params()?;
+        options.sbox = params.sbox;
+        options.layout = params.layout;
+    }
+
+    if args.aead {
+        return Ok(options);
+    }
+
+    match args.mode {
+        Mode::Cfb => {
+            options.segment = args.segment_bits.map(|bits| (bits / 8) as u8);
+            options.key_meshing = args.key_meshing;
+        }
+        Mode::Ecb | Mode::Cbc => options.padding = args.padding,
+        Mode::AcpkmCtr => {
+            options.section_size = args
+                .section_size
+                .try_into()
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, "the section size is too large"))?
+        }
+        Mode::Ctr | Mode::Ofb => {}
+    }
+
+    Ok(options)
+}
+
+/// Numbers of `N` bytes the IV is made of
+fn iv_numbers<const N: usize>(iv: &[u8]) -> impl Iterator<Item = [u8; N]> + '_ {
+    iv.chunks_exact(N).map(|number| number.try_into().unwrap())
+}
+
+/// Passes the first `left` bytes through and drops the rest,
+/// which cuts the zero padding off with the length from the header
+struct Truncating<W> {
+    inner: W,
+    left: u64,
+}
+
+impl<W: Write> Write for Truncating<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = buf.len().min(self.left.try_into().unwrap_or(usize::MAX));
+
+        self.inner.write_all(&buf[..len])?;
+        self.left -= len as u64;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Runs `transform` on the numbers the blocks stand for in big-endian order
+fn in_be_order(blocks: &mut [u128], transform: impl FnOnce(&mut [u128])) {
+    blocks.iter_mut().for_each(|b| *b = u128::from_be(*b));
+    transform(blocks);
+    blocks.iter_mut().for_each(|b| *b = u128::to_be(*b));
+}
+
+/// Transforms the input buffer by buffer, the output is as long as the input
+fn stream<T: Block>(
+    input: &mut Input,
+    output: &mut impl Write,
+    buff: &mut BlockBuffer<T>,
+    mut transform: impl FnMut(&mut BlockBuffer<T>),
+) -> Result<()> {
+    loop {
+        let read = buff.read_bytes_from(input)?;
+
+        if read == 0 {
+            return Ok(());
+        }
+
+        transform(buff);
+
+        output.write_all(&buff.as_bytes()[..read])?;
+    }
+}
+
+fn padded_encrypt<T: Block>(
+    input: &mut Input,
+    output: &mut impl Write,
+    buff: &mut BlockBuffer<T>,
+    padding: Padding,
+    mut transform: impl FnMut(&mut [T]),
+) -> Result<()> {
+    let block_size = std::mem::size_of::<T>();
+
+    loop {
+        let read = buff.read_bytes_from(input)?;
+        let whole = read / block_size;
+
+        transform(&mut buff[..whole]);
+        output.write_all(&buff.as_bytes()[..whole * block_size])?;
+
+        if read != 0 && read % block_size == 0 {
+            continue;
+        }
+
+        let mut last = vec![0u8; block_size];
+
+        if padding.pad(&buff.as_bytes()[whole * block_size..read], &mut last)? {
+            let mut block = BlockBuffer::<T>::new(block_size);
+            block.read_bytes_from(&mut last.as_slice())?;
+            transform(&mut block);
+            output.write_all(block.as_bytes())?;
+        }
+
+        return Ok(());
+    }
+}
+
+fn padded_decrypt<T: Block>(
+    input: &mut Input,
+    output: &mut impl Write,
+    buff: &mut BlockBuffer<T>,
+    padding: Padding,
+    mut transform: impl FnMut(&mut [T]),
+) -> Result<()> {
+    let block_size = std::mem::size_of::<T>();
+
+    // The last block is held back until it is known to be the last one
+    let mut pending = Vec::new();
+
+    loop {
+        let read = buff.read_bytes_from(input)?;
+
+        if read == 0 {
+            break;
+        }
+
+        if read % block_size != 0 {
+            return Err(PaddingError::Unaligned.into());
+        }
+
+        transform(buff);
+
+        output.write_all(&pending)?;
+
+        let (data, last) = buff.as_bytes()[..read].split_at(read - block_size);
+        output.write_all(data)?;
+        pending = last.to_vec();
+    }
+
+    if pending.is_empty() {
+        if matches!(padding, Padding::Pkcs7 | Padding::Iso7816) {
+            return Err(PaddingError::Invalid.into());
+        }
+        return Ok(());
+    }
+
+    let len = padding.unpad(&pending)?;
+    output.write_all(&pending[..len])
+}
+
+fn compute_mac(input: PathBuf, key: &KeyArgs, len: usize) -> Result<Vec<u8>> {
+    let mut file = Input::open(&input)?;
+    let mut buff = BlockBuffer::<u64>::new(buff_size(file.known_len(), MAX_BUFF_SIZE));
+
+    let params = key.params()?;
+    let mut mac = Imitovstavka::new(key.key()?, params);
+
+    loop {
+        let read = buff.read_bytes_from(&mut file)?;
+        let whole = read / BLOCK_SIZE;
+
+        params.order.convert(&mut buff[..whole]);
+        mac.process_blocks(&buff[..whole]);
+
+        if read == 0 || read % BLOCK_SIZE != 0 {
+            return Ok(mac.finish(&buff.as_bytes()[whole * BLOCK_SIZE..read], len));
+        }
+    }
+}
+
+/// Parameters of the key wrap, RFC 4357 uses the CryptoPro A S-boxes
+fn wrap_params(key: &KeyArgs) -> Result<Params> {
+    if key.cipher != Cipher::Gost28147 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "keys are wrapped with gost28147 keys only",
+        ));
+    }
+
+    Ok(Params {
+        sbox: key.sbox.unwrap_or(SBoxSet::CRYPTO_PRO_A),
+        ..key.params()?
+    })
+}
+
+/// Prints the trails of `simple_swap_rounds` and the pairs that followed them under
+/// a random key. With `attack` the last subkey of the cipher with a round more is
+/// recovered and compared with the real one.
+fn trail(params: &Params, rounds: usize, width: usize, pairs: usize, attack: bool) {
+    let key = MagmaKey::new(rand::random());
+    let trails = gost28147_trails(params, rounds, width);
+
+    for trail in &trails {
+        let differences: Vec<_> = trail
+            .differences
+            .iter()
+            .map(|difference| format!("{difference:016x}"))
+            .collect();
+
+        let right = right_pairs(trail, pairs, &mut rand::thread_rng(), |x| {
+            simple_swap_rounds(x, &key, rounds, params)
+        });
+
+        println!(
+            "{} weight {:.2}: {right} of {pairs} pairs, {:.2} expected",
+            differences.join(" -> "),
+            trail.weight,
+            pairs as f64 * (-trail.weight).exp2()
+        );
+    }
+
+    if !attack {
+        return;
+    }
+
+    let Some(attack) =
+        gost28147_last_round_attack(&trails, params, pairs, &mut rand::thread_rng(), |x| {
+            simple_swap_rounds(x, &key, rounds + 1, params)
+        })
+    else {
+        return;
+    };
+
+    let subkey = key.round_key(rounds);
+
+    println!();
+    println!(
+        "attack on {} rounds with the trail from {:016x}, {} pairs left after filtering",
+        rounds + 1,
+        attack.trail.input(),
+        attack.filtered
+    );
+
+    for (n, candidates) in attack.candidates.iter().enumerate() {
+        if !candidates.is_empty() {
+            let candidates: Vec<_> = candidates.iter().map(|c| format!("{c:x}")).collect();
+            println!(
+                "nibble {n}: {}, the real one is {:x}",
+                candidates.join(" "),
+                subkey >> (n * 4) & 0xf
+            );
+        }
+    }
+
+    println!(
+        "subkey {subkey:08x} {}, 2^{:.1} subkeys left",
+        if attack.matches(subkey) {
+            "is among the candidates"
+        } else {
+            "is missed"
+        },
+        (attack.subkeys_left() as f64).log2()
+    );
+}
+
+/// An S-box output, decimal or hex with `0x`
+fn parse_sbox_value(value: &str) -> std::result::Result<u8, String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    parsed.map_err(|e| e.to_string())
+}
+
+fn format_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, "invalid hex string");
+
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let mut args = match cli.command {
+        Some(Commands::Mac {
+            input,
+            mac_len,
+            key,
+        }) => {
+            let mac = compute_mac(input, &key, mac_len as usize)?;
+            println!("{}", format_hex(&mac));
+            return Ok(());
+        }
+        Some(Commands::VerifyMac { input, mac, key }) => {
+            let expected = parse_hex(&mac)?;
+
+            if !(1..=MAX_MAC_LEN).contains(&expected.len()) {
+                return Err(Error::new(ErrorKind::InvalidInput, "invalid MAC length"));
+            }
+
+            if !mac_eq(&compute_mac(input, &key, expected.len())?, &expected) {
+                return Err(Error::new(ErrorKind::InvalidData, "MAC doesn't match"));
+            }
+
+            println!("MAC is verified");
+            return Ok(());
+        }
+        Some(Commands::WrapKey {
+            input,
+            output,
+            cryptopro,
+            ukm,
+            key,
+        }) => {
+            let params = wrap_params(&key)?;
+
+            let ukm = match ukm {
+                Some(hex) => parse_hex(&hex)?
+                    .try_into()
+                    .map_err(|_| Error::new(ErrorKind::InvalidInput, "UKM must be 8 bytes"))?,
+                None => rand::random(),
+            };
+
+            let wrap = if cryptopro {
+                cryptopro_wrap
+            } else {
+                gost28147_wrap
+            };
+
+            let cek = KeyFile::read(input)?;
+            return std::fs::write(output, wrap(&key.key()?, &ukm, &cek.key, &params));
+        }
+        Some(Commands::UnwrapKey {
+            input,
+            output,
+            cryptopro,
+            key,
+        }) => {
+            let params = wrap_params(&key)?;
+
+            let wrapped: [u8; WRAPPED_KEY_LEN] =
+                std::fs::read(input)?.try_into().map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("a wrapped key must be {WRAPPED_KEY_LEN} bytes"),
+                    )
+                })?;
+
+            let unwrap = if cryptopro {
+                cryptopro_unwrap
+            } else {
+                gost28147_unwrap
+            };
+
+            let cek = Zeroizing::new(unwrap(&key.key()?, &wrapped, &params)?);
+            return std::fs::write(output, &cek[..]);
+        }
+        Some(Commands::AnalyzeSbox {
+            sbox,
+            kuznyechik,
+            candidate,
+            tables,
+        }) => {
+            let sboxes: Vec<(String, Vec<u8>)> = match candidate {
+                Some(candidate) => vec![("candidate".into(), candidate)],
+                None if kuznyechik => vec![("kuznyechik".into(), Kuznyechik::S_BOX.to_vec())],
+                None => {
+                    let set = sbox.unwrap_or_default();
+                    (0..set.tables.len())
+                        .map(|i| (format!("{} table {i}", set.name), set.tables[i].to_vec()))
+                        .collect()
+                }
+            };
+
+            for (name, sbox) in sboxes {
+                let report = SBoxReport::new(&sbox)?;
+                println!("{name}: {report}");
+
+                if tables {
+                    report.write_tables(&mut stdout().lock())?;
+                }
+                println!();
+            }
+
+            return Ok(());
+        }
+        Some(Commands::Trail {
+            rounds,
+            sbox,
+            legacy,
+            width,
+            pairs,
+            attack,
+        }) => {
+            if attack && rounds == 31 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "the attack needs a round more than the trails, so 30 rounds of them at most",
+                ));
+            }
+
+            // The key is thrown away, so the pairs go through the faster tables
+            let params = Params {
+                layout: if legacy {
+                    Layout::Legacy
+                } else {
+                    Layout::Standard
+                },
+                backend: Backend::Table,
+                ..Params::new(sbox.unwrap_or_default())
+            };
+
+            trail(&params, rounds as usize, width as usize, pairs, attack);
+            return Ok(());
+        }
+        None => cli.args,
+    };
+
+    // Clap requires both when there is no subcommand
+    let (Some(input), Some(output)) = (args.input.clone(), args.output.clone()) else {
+        unreachable!()
+    };
+
+    let mut file = Input::open(&input)?;
+
+    let len = file.known_len();
+
+    let mut header = if args.no_header {
+        None
+    } else {
+        Some(header(&mut args, &mut file, len)?)
+    };
+
+    let iv = match &header {
+        Some(header) => header.iv.clone(),
+        None => legacy_iv(&args)?,
+    };
+
+    if let Some(kdf) = header.as_mut().and_then(|header| header.kdf.as_mut()) {
+        if args.key.uses_passphrase() {
+            let passphrase = lab5::read_passphrase(!args.decrypt)?;
+            let key = lab5::derive_key(passphrase.as_bytes(), &kdf.salt, kdf.iterations);
+            let check_value = args.key.check_value(&key)?;
+
+            if args.decrypt {
+                kdf.verify(check_value)?;
+            } else {
+                kdf.check_value = check_value;
+            }
+
+            args.key.derived = Some(key);
+        }
+    }
+
+    if args.aead {
+        return aead(&args, file, &output, header.as_ref(), &iv, len);
+    }
+
+    if let Some(range) = args.range {
+        return decrypt_range(&args, file, &output, header.as_ref(), &iv, range);
+    }
+
+    if args.key_meshing && args.key.cipher != Cipher::Gost28147 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "key meshing is defined for gost28147 only",
+        ));
+    }
+
+    let mut out_file = create_output(&output)?;
+
+    // Only the plaintext has a known length to cut the output to,
+    // a stream ends with its ciphertext
+    let left = match &header {
+        Some(header) if args.decrypt => header.known_len().unwrap_or(u64::MAX),
+        Some(header) => {
+            header.write_to(&mut out_file)?;
+            u64::MAX
+        }
+        None => u64::MAX,
+    };
+    let mut output = Truncating {
+        inner: out_file,
+        left,
+    };
+
+    match args.key.cipher {
+        Cipher::Gost28147 | Cipher::Magma => encrypt_gost(&args, &mut file, &mut output, &iv, len)?,
+        Cipher::Kuznyechik => encrypt_kuznyechik(&args, &mut file, &mut output, &iv, len)?,
+    }
+
+    let known_len = header.as_ref().and_then(Header::known_len);
+    if known_len.is_some() && args.decrypt && output.left != 0 {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "the ciphertext is shorter than its header says",
+        ));
+    }
+
+    output.flush()
+}
+
+/// Decrypts a part of a ctr mode ciphertext, the gamma starts right at it
+fn decrypt_range(
+    args: &Args,
+    input: Input,
+    output: &Path,
+    header: Option<&Header>,
+    iv: &[u8],
+    range: ByteRange,
+) -> Result<()> {
+    if !matches!(args.mode, Mode::Ctr) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "a range can be decrypted in ctr mode only",
+        ));
+    }
+
+    let end = range.offset.checked_add(range.len);
+    let known_len = header.and_then(Header::known_len);
+    if known_len.is_some_and(|len| end.is_none_or(|end| end > len)) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the range is past the end of the plaintext",
+        ));
+    }
+
+    let input = input.seekable()?;
+
+    match args.key.cipher {
+        Cipher::Gost28147 => {
+            let iv = u64::from_be_bytes(iv.try_into().unwrap());
+            let gamma = Gost28147Gamma::new(args.key.key()?, iv, args.key.params()?);
+            copy_range(gamma, input, output, range)
+        }
+        Cipher::Magma => {
+            let iv = u32::from_be_bytes(iv.try_into().unwrap());
+            let cipher = Gost28147::with_params(args.key.key()?, args.key.params()?);
+            let gamma = CounterGamma::new(cipher, magma_counter_init(iv));
+            copy_range(gamma, input, output, range)
+        }
+        Cipher::Kuznyechik => {
+            let iv = u64::from_be_bytes(iv.try_into().unwrap());
+            args.key.check_options()?;
+            let cipher = Kuznyechik::new(&*args.key.key_bytes()?);
+            let gamma = CounterGamma::new(cipher, kuznyechik_counter_init(iv));
+            copy_range(gamma, input, output, range)
+        }
+    }
+}
+
+fn copy_range<G: Gamma>(gamma: G, input: Input, output: &Path, range: ByteRange) -> Result<()> {
+    let mut reader = CtrDecryptReader::new(gamma, input)?;
+    reader.seek(SeekFrom::Start(range.offset))?;
+
+    let mut output = create_output(output)?;
+    copy(&mut reader.take(range.len), &mut output)?;
+    output.flush()
+}
+
+fn encrypt_gost(
+    args: &Args,
+    input: &mut Input,
+    output: &mut impl Write,
+    iv: &[u8],
+    len: Option<u64>,
+) -> Result<()> {
+    let key = args.key.key()?;
+    let params = args.key.params()?;
+    let order = params.order;
+    let threads = args.threads.get();
+
+    let (_, segment) = feedback_sizes(args, BLOCK_SIZE)?;
+    let mut register: Vec<u64> = iv_numbers(iv).map(u64::from_be_bytes).collect();
+
+    let mut buff = BlockBuffer::new(buff_size(len, MAX_BUFF_SIZE));
+    let buff = &mut buff;
+
+    let plain_cfb = matches!(args.mode, Mode::Cfb) && register.len() == 1 && segment == BLOCK_SIZE;
+
+    if args.key_meshing && !plain_cfb {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "key meshing needs cfb mode with a block of register and segment",
+        ));
+    }
+
+    match (args.mode, args.decrypt) {
+        (Mode::Cfb, decrypt) if args.key_meshing => {
+            let transform_func = if decrypt {
+                cipher_feedback_mode_block_meshed_decrypt
+            } else {
+                cipher_feedback_mode_block_meshed
+            };
+
+            let mut state = MeshedCfb::new(key, register[0]);
+            stream(input, output, buff, |blocks| {
+                transform_func(&mut state, blocks, &params)
+            })
+        }
+        (Mode::Cfb, decrypt) if plain_cfb => {
+            let transform_func = if decrypt {
+                cipher_feedback_mode_block_decrypt
+            } else {
+                cipher_feedback_mode_block
+            };
+
+            let mut prev = register[0];
+            stream(input, output, buff, |blocks| {
+                in_order(order, blocks, |blocks| {
+                    prev = transform_func(prev, blocks, &key, &params)
+                })
+            })
+        }
+        (Mode::Cfb, decrypt) => {
+            let transform_func = if decrypt {
+                cipher_feedback_mode_segments_decrypt
+            } else {
+                cipher_feedback_mode_segments
+            };
+
+            let mut register: Vec<u8> = register.iter().flat_map(|&b| order.bytes(b)).collect();
+            stream(input, output, buff, |blocks| {
+                transform_func(&mut register, segment, blocks.as_bytes_mut(), &key, &params)
+            })
+        }
+        // Magma has the counter mode of GOST R 34.13-2015, not the gamming of 28147-89
+        (Mode::Ctr, _) if args.key.cipher == Cipher::Magma => {
+            let iv = u32::from_be_bytes(iv.try_into().unwrap());
+            let cipher = Gost28147::with_params(key, params);
+            let mut counter = magma_counter_init(iv);
+            stream(input, output, buff, |blocks| {
+                in_order(order, blocks, |blocks| {
+                    counter = counter_parallel(&cipher, counter, blocks, threads)
+                })
+            })
+        }
+        (Mode::Ctr, _) => {
+            let iv = u64::from_be_bytes(iv.try_into().unwrap());
+            let mut counter = counter_mode_init(iv, &key, &params);
+            stream(input, output, buff, |blocks| {
+                in_order(order, blocks, |blocks| {
+                    counter = counter_mode_block_parallel(counter, blocks, &key, &params, threads)
+                })
+            })
+        }
+        (Mode::AcpkmCtr, _) => {
+            let iv = u32::from_be_bytes(iv.try_into().unwrap());
+            let mut state = AcpkmCtr::new(key, iv, section_blocks(args, BLOCK_SIZE)?);
+            stream(input, output, buff, |blocks| {
+                in_order(order, blocks, |blocks| {
+                    acpkm_counter_mode_block(&mut state, blocks, &params)
+                })
+            })
+        }
+        (Mode::Ofb, _) => stream(input, output, buff, |blocks| {
+            in_order(order, blocks, |blocks| {
+                output_feedback_mode_block(&mut register, blocks, &key, &params)
+            })
+        }),
+        (Mode::Ecb, false) => padded_encrypt(input, output, buff, args.padding, |blocks| {
+            in_order(order, blocks, |blocks| {
+                simple_replacement_mode_block_parallel(blocks, &key, &params, threads)
+            })
+        }),
+        (Mode::Ecb, true) => padded_decrypt(input, output, buff, args.padding, |blocks| {
+            in_order(order, blocks, |blocks| {
+                simple_replacement_mode_block_decrypt_parallel(blocks, &key, &params, threads)
+            })
+        }),
+        (Mode::Cbc, false) => padded_encrypt(input, output, buff, args.padding, |blocks| {
+            in_order(order, blocks, |blocks| {
+                cipher_block_chaining_mode_block(&mut register, blocks, &key, &params)
+            })
+        }),
+        (Mode::Cbc, true) => padded_decrypt(input, output, buff, args.padding, |blocks| {
+            in_order(order, blocks, |blocks| {
+                cipher_block_chaining_mode_block_decrypt(&mut register, blocks, &key, &params)
+            })
+        }),
+    }
+}
+
+fn encrypt_kuznyechik(
+    args: &Args,
+    input: &mut Input,
+    output: &mut impl Write,
+    iv: &[u8],
+    len: Option<u64>,
+) -> Result<()> {
+    args.key.check_options()?;
+    let cipher = Kuznyechik::new(&*args.key.key_bytes()?);
+    let threads = args.threads.get();
+
+    let (_, segment) = feedback_sizes(args, 16)?;
+    let mut register: Vec<u128> = iv_numbers(iv).map(u128::from_be_bytes).collect();
+
+    let mut buff = BlockBuffer::<u128>::new(buff_size(len, MAX_BUFF_SIZE));
+    let buff = &mut buff;
+
+    match (args.mode, args.decrypt) {
+        (Mode::Cfb, decrypt) => {
+            let transform_func = if decrypt {
+                kuznyechik_cipher_feedback_decrypt
+            } else {
+                kuznyechik_cipher_feedback
+            };
+
+            let mut register: Vec<u8> = register.iter().flat_map(|b| b.to_be_bytes()).collect();
+            stream(input, output, buff, |blocks| {
+                transform_func(&cipher, &mut register, segment, blocks.as_bytes_mut())
+            })
+        }
+        (Mode::Ctr, _) => {
+            let iv = u64::from_be_bytes(iv.try_into().unwrap());
+            let mut counter = kuznyechik_counter_init(iv);
+            stream(input, output, buff, |blocks| {
+                in_be_order(blocks, |blocks| {
+                    counter = counter_parallel(&cipher, counter, blocks, threads)
+                })
+            })
+        }
+        (Mode::AcpkmCtr, _) => {
+            let section = section_blocks(args, 16)?;
+            let iv = u64::from_be_bytes(iv.try_into().unwrap());
+            let mut state = KuznyechikAcpkmCtr::new(cipher.clone(), iv, section);
+            stream(input, output, buff, |blocks| {
+                in_be_order(blocks, |blocks| {
+                    kuznyechik_acpkm_counter(&mut state, blocks)
+                })
+            })
+        }
+        (Mode::Ofb, _) => stream(input, output, buff, |blocks| {
+            in_be_order(blocks, |blocks| {
+                kuznyechik_output_feedback(&cipher, &mut register, blocks)
+            })
+        }),
+        (Mode::Ecb, false) => padded_encrypt(input, output, buff, args.padding, |blocks| {
+            in_be_order(blocks, |blocks| {
+                simple_replacement_parallel(&cipher, blocks, threads)
+            })
+        }),
+        (Mode::Ecb, true) => padded_decrypt(input, output, buff, args.padding, |blocks| {
+            in_be_order(blocks, |blocks| {
+                simple_replacement_decrypt_parallel(&cipher, blocks, threads)
+            })
+        }),
+        (Mode::Cbc, false) => padded_encrypt(input, output, buff, args.padding, |blocks| {
+            in_be_order(blocks, |blocks| {
+                kuznyechik_cipher_block_chaining(&cipher, &mut register, blocks)
+            })
+        }),
+        (Mode::Cbc, true) => padded_decrypt(input, output, buff, args.padding, |blocks| {
+            in_be_order(blocks, |blocks| {
+                kuznyechik_cipher_block_chaining_decrypt(&cipher, &mut register, blocks)
+            })
+        }),
+    }
+}
+
+fn aead(
+    args: &Args,
+    input: Input,
+    output: &Path,
+    header: Option<&Header>,
+    nonce: &[u8],
+    len: Option<u64>,
+) -> Result<()> {
+    if args.key.cipher == Cipher::Kuznyechik {
+        args.key.check_options()?;
+        let cipher = Kuznyechik::new(&*args.key.key_bytes()?);
+        let nonce = MgmBlock::from_bytes(nonce);
+
+        return aead_file(args, input, output, header, len, nonce, |block| {
+            cipher.encrypt(block)
+        });
+    }
+
+    let key = args.key.key()?;
+    let params = args.key.params()?;
+    let order = params.order;
+    let nonce = MgmBlock::from_bytes(nonce);
+
+    aead_file(args, input, output, header, len, nonce, |block: u64| {
+        let block = order.block(block.to_be_bytes());
+        u64::from_be_bytes(order.bytes(simple_swap_with(block, &key, &params)))
+    })
+}
+
+fn aead_file<B: MgmBlock + Block>(
+    args: &Args,
+    mut input: Input,
+    output: &Path,
+    header: Option<&Header>,
+    len: Option<u64>,
+    nonce: B,
+    encrypt: impl Fn(B) -> B + Clone,
+) -> Result<()> {
+    // Every chunk but the last one must be whole blocks of the mode
+    let mut buff = BlockBuffer::<B>::new(buff_size(len, MAX_BUFF_SIZE));
+
+    let associated = match &args.aad {
+        Some(path) => std::fs::read(path)?,
+        None => Vec::new(),
+    };
+
+    let mut mgm = Mgm::new(encrypt.clone(), nonce);
+    mgm.associated_data(&associated);
+
+    if !args.decrypt {
+        let mut out_file = create_output(output)?;
+
+        if let Some(header) = header {
+            header.write_to(&mut out_file)?;
+        }
+
+        loop {
+            let read = buff.read_bytes_from(&mut input)?;
+
+            if read == 0 {
+                break;
+            }
+
+            let data = &mut buff.as_bytes_mut()[..read];
+            mgm.encrypt(data);
+            out_file.write_all(data)?;
+        }
+
+        out_file.write_all(&mgm.finish(B::SIZE))?;
+        return out_file.flush();
+    }
+
+    // The tag is checked before the ciphertext is decrypted, a stream is read to memory for that
+    let mut input = input.seekable()?;
+    let start = input.stream_position()?;
+    let end = input.seek(SeekFrom::End(0))?;
+    input.seek(SeekFrom::Start(start))?;
+
+    let data_len = (end - start)
+        .checked_sub(B::SIZE as u64)
+        .ok_or(TagMismatch)?;
+
+    if header
+        .and_then(Header::known_len)
+        .is_some_and(|len| len != data_len)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "the ciphertext length doesn't match its header",
+        ));
+    }
+
+    // The whole ciphertext is authenticated before any plaintext is written
+    let mut ciphertext = (&mut input).take(data_len);
+
+    loop {
+        let read = buff.read_bytes_from(&mut ciphertext)?;
+
+        if read == 0 {
+            break;
+        }
+
+        mgm.authenticate(&buff.as_bytes()[..read]);
+    }
+
+    let mut tag = vec![0; B::SIZE];
+    input.read_exact(&mut tag)?;
+    mgm.verify(&tag)?;
+
+    input.seek(SeekFrom::Start(start))?;
+    let mut ciphertext = input.take(data_len);
+
+    let mut out_file = create_output(output)?;
+    let mut mgm = Mgm::new(encrypt, nonce);
+
+    loop {
+        let read = buff.read_bytes_from(&mut ciphertext)?;
+
+        if read == 0 {
+            break;
+        }
+
+        let data = &mut buff.as_bytes_mut()[..read];
+        mgm.keystream(data);
+        out_file.write_all(data)?;
+    }
+
+    out_file.flush()
+}
@@ -0,0 +1,137 @@
+use super::simple_swap::*;
+
+/// Blocks D1..D4 of RFC 8645: the bytes 0x80..0x9f
+const ACPKM_CONSTANT: [u8; 32] = {
+    let mut d = [0; 32];
+    let mut i = 0;
+    while i < 32 {
+        d[i] = 0x80 + i as u8;
+        i += 1;
+    }
+    d
+};
+
+/// ACPKM of RFC 8645: the next key is D1..D4 encrypted with the current one
+pub fn acpkm_key(key: &MagmaKey, params: &Params) -> MagmaKey {
+    let bytes: Vec<u8> = ACPKM_CONSTANT
+        .chunks_exact(8)
+        .flat_map(|d| {
+            let block = params.order.block(d.try_into().unwrap());
+            params.order.bytes(simple_swap_with(block, key, params))
+        })
+        .collect();
+
+    MagmaKey::new(std::array::from_fn(|i| {
+        params
+            .order
+            .word(bytes[i * 4..i * 4 + 4].try_into().unwrap())
+    }))
+}
+
+/// Counter mode of GOST R 34.13-2015 where the key goes through ACPKM
+/// after every section, the counter runs on across sections
+#[derive(Debug, Clone)]
+pub struct AcpkmCtr {
+    key: MagmaKey,
+    counter: u64,
+    section: usize,
+    used: usize,
+}
+
+impl AcpkmCtr {
+    /// The first counter is the `iv` followed by zeroes, sections are given in blocks
+    pub fn new(key: MagmaKey, iv: u32, section: usize) -> Self {
+        assert!(section > 0, "section must hold a block");
+
+        Self {
+            key,
+            counter: (iv as u64) << 32,
+            section,
+            used: 0,
+        }
+    }
+}
+
+/// Encryption and decryption are the same operation
+pub fn acpkm_counter_mode_block(state: &mut AcpkmCtr, input: &mut [u64], params: &Params) {
+    input.iter_mut().for_each(|block| {
+        if state.used == state.section {
+            state.key = acpkm_key(&state.key, params);
+            state.used = 0;
+        }
+
+        *block ^= simple_swap_with(state.counter, &state.key, params);
+        state.counter = state.counter.wrapping_add(1);
+        state.used += 1;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Magma;
+
+    /// The key of RFC 8891
+    fn magma_key() -> MagmaKey {
+        let key: [u8; 32] = std::array::from_fn(|i| match i {
+            0..=15 => 0xff - i as u8 * 0x11,
+            _ => 0xf0 + (i as u8 - 16),
+        });
+        Magma::new(&key).key().clone()
+    }
+
+    /// The key of the examples of RFC 8645
+    fn rfc_8645_key() -> MagmaKey {
+        let mut key = [0; 32];
+        key[..16].copy_from_slice(&0x8899aabbccddeeff0011223344556677u128.to_be_bytes());
+        key[16..].copy_from_slice(&0xfedcba98765432100123456789abcdefu128.to_be_bytes());
+        Magma::new(&key).key().clone()
+    }
+
+    #[test]
+    fn acpkm_key_known_answer() {
+        let next = acpkm_key(&magma_key(), &Magma::PARAMS);
+
+        assert_eq!(
+            next.words(),
+            [
+                0xed31b095, 0xb894d449, 0x7be284b8, 0x4dba847c, 0x5991ce33, 0x1d549edd, 0x403ca827,
+                0x4a7d3fcf
+            ]
+        );
+    }
+
+    #[test]
+    fn acpkm_ctr_known_answer() {
+        // RFC 8645 appendix A.2: sections of N = 16 bytes, IV 12345678
+        let mut blocks = [
+            0x1122334455667700,
+            0xffeeddccbbaa9988,
+            0x0011223344556677,
+            0x8899aabbcceeff0a,
+            0x1122334455667788,
+            0x99aabbcceeff0a00,
+            0x2233445566778899,
+            0xaabbcceeff0a0011,
+        ];
+
+        let mut state = AcpkmCtr::new(rfc_8645_key(), 0x12345678, 2);
+        let (head, tail) = blocks.split_at_mut(3);
+        acpkm_counter_mode_block(&mut state, head, &Magma::PARAMS);
+        acpkm_counter_mode_block(&mut state, tail, &Magma::PARAMS);
+
+        assert_eq!(
+            blocks,
+            [
+                0x2ab81deeeb1e4cab,
+                0x68e104c4bd6b94ea,
+                0xc72c67af6c2e5b6b,
+                0x0eafb61770f1b32e,
+                0xa1ae71149eed1382,
+                0xabd467180672ec6f,
+                0x84a2f15b3fca72c1,
+                0x5559fbd38c4c7c5d,
+            ]
+        );
+    }
+}
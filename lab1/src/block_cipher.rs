@@ -0,0 +1,80 @@
+use std::ops::BitXor;
+
+use super::simple_swap::ByteOrder;
+
+/// A block taken as a number, as the modes of operation see it
+pub trait CipherBlock: Copy + Default + PartialEq + BitXor<Output = Self> + Send + Sync {
+    const SIZE: usize;
+
+    /// Adds one modulo 2^n, the counter step of GOST R 34.13-2015
+    fn increment(self) -> Self;
+    /// Adds `blocks` modulo 2^n, the counter `blocks` steps later
+    fn advance(self, blocks: u64) -> Self;
+    /// Reads the block from `SIZE` bytes
+    fn from_bytes(bytes: &[u8], order: ByteOrder) -> Self;
+    /// Writes the block to `SIZE` bytes
+    fn write_bytes(self, bytes: &mut [u8], order: ByteOrder);
+}
+
+macro_rules! cipher_block {
+    ($block:ty) => {
+        impl CipherBlock for $block {
+            const SIZE: usize = std::mem::size_of::<$block>();
+
+            fn increment(self) -> Self {
+                self.wrapping_add(1)
+            }
+
+            fn advance(self, blocks: u64) -> Self {
+                self.wrapping_add(blocks as Self)
+            }
+
+            fn from_bytes(bytes: &[u8], order: ByteOrder) -> Self {
+                let bytes = bytes.try_into().unwrap();
+                match order {
+                    ByteOrder::Little => Self::from_le_bytes(bytes),
+                    ByteOrder::Big => Self::from_be_bytes(bytes),
+                }
+            }
+
+            fn write_bytes(self, bytes: &mut [u8], order: ByteOrder) {
+                bytes.copy_from_slice(&match order {
+                    ByteOrder::Little => self.to_le_bytes(),
+                    ByteOrder::Big => self.to_be_bytes(),
+                });
+            }
+        }
+    };
+}
+
+cipher_block!(u64);
+cipher_block!(u128);
+
+/// A block cipher the generic modes of operation run on
+pub trait BlockCipher {
+    type Block: CipherBlock;
+    /// What the cipher is made from
+    type Key: ?Sized;
+
+    fn new(key: &Self::Key) -> Self;
+    fn encrypt(&self, block: Self::Block) -> Self::Block;
+    fn decrypt(&self, block: Self::Block) -> Self::Block;
+
+    /// Encrypts the blocks in place, for ciphers faster on many blocks at once
+    fn encrypt_blocks(&self, blocks: &mut [Self::Block]) {
+        blocks
+            .iter_mut()
+            .for_each(|block| *block = self.encrypt(*block));
+    }
+
+    fn decrypt_blocks(&self, blocks: &mut [Self::Block]) {
+        blocks
+            .iter_mut()
+            .for_each(|block| *block = self.decrypt(*block));
+    }
+
+    /// How blocks are packed into bytes, big-endian as in GOST R 34.12-2015
+    fn byte_order(&self) -> ByteOrder {
+        ByteOrder::Big
+    }
+}
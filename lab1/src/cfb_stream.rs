@@ -0,0 +1,191 @@
+use std::io::{self, Read, Write};
+
+use super::block_cipher::*;
+
+/// Largest piece a `write` call encrypts at once
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// Cipher feedback of whole blocks over a byte stream: the gamma of a block
+/// is used byte by byte, so a short last block takes only a part of it
+#[derive(Debug, Clone)]
+struct CfbStream<C: BlockCipher> {
+    cipher: C,
+    gamma: Vec<u8>,
+    /// Ciphertext of the current block, the next register
+    feedback: Vec<u8>,
+    used: usize,
+}
+
+impl<C: BlockCipher> CfbStream<C> {
+    fn new(cipher: C, iv: C::Block) -> Self {
+        let size = C::Block::SIZE;
+        let mut feedback = vec![0; size];
+        iv.write_bytes(&mut feedback, cipher.byte_order());
+
+        Self {
+            cipher,
+            gamma: vec![0; size],
+            feedback,
+            used: size,
+        }
+    }
+
+    fn apply(&mut self, data: &mut [u8], decrypt: bool) {
+        let order = self.cipher.byte_order();
+
+        for byte in data {
+            if self.used == self.gamma.len() {
+                let register = C::Block::from_bytes(&self.feedback, order);
+                self.cipher
+                    .encrypt(register)
+                    .write_bytes(&mut self.gamma, order);
+                self.used = 0;
+            }
+
+            let encrypted = if decrypt {
+                *byte
+            } else {
+                *byte ^ self.gamma[self.used]
+            };
+            *byte ^= self.gamma[self.used];
+            self.feedback[self.used] = encrypted;
+            self.used += 1;
+        }
+    }
+}
+
+/// Encrypts everything written to it in CFB mode and writes it to `inner`.
+///
+/// Writes of any size continue the same stream, the ciphertext has
+/// the length of the plaintext.
+#[derive(Debug)]
+pub struct CfbEncryptWriter<C: BlockCipher, W: Write> {
+    stream: CfbStream<C>,
+    inner: W,
+    buff: Vec<u8>,
+}
+
+impl<C: BlockCipher, W: Write> CfbEncryptWriter<C, W> {
+    pub fn new(cipher: C, iv: C::Block, inner: W) -> Self {
+        Self {
+            stream: CfbStream::new(cipher, iv),
+            inner,
+            buff: Vec::new(),
+        }
+    }
+
+    /// Flushes the writer and gives back the inner one
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// An error of the inner writer leaves the stream out of sync
+impl<C: BlockCipher, W: Write> Write for CfbEncryptWriter<C, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(CHUNK_SIZE);
+
+        self.buff.clear();
+        self.buff.extend_from_slice(&buf[..len]);
+        self.stream.apply(&mut self.buff, false);
+        self.inner.write_all(&self.buff)?;
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads a CFB ciphertext from `inner` and decrypts it on the fly
+#[derive(Debug)]
+pub struct CfbDecryptReader<C: BlockCipher, R: Read> {
+    stream: CfbStream<C>,
+    inner: R,
+}
+
+impl<C: BlockCipher, R: Read> CfbDecryptReader<C, R> {
+    pub fn new(cipher: C, iv: C::Block, inner: R) -> Self {
+        Self {
+            stream: CfbStream::new(cipher, iv),
+            inner,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<C: BlockCipher, R: Read> Read for CfbDecryptReader<C, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.stream.apply(&mut buf[..len], true);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cipher_feedback_mode_block, Gost28147, Kuznyechik, MagmaKey, Params};
+
+    fn gost() -> Gost28147 {
+        Gost28147::with_params(
+            MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]),
+            Params::default(),
+        )
+    }
+
+    #[test]
+    fn same_as_block_mode() {
+        let plain: Vec<u8> = (0..45).collect();
+
+        let mut blocks: Vec<u64> = plain
+            .chunks(8)
+            .map(|c| {
+                let mut block = [0; 8];
+                block[..c.len()].copy_from_slice(c);
+                u64::from_le_bytes(block)
+            })
+            .collect();
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        cipher_feedback_mode_block(0xBADF00D, &mut blocks, &key, &Params::default());
+        let expected: Vec<u8> = blocks.iter().flat_map(|b| b.to_le_bytes()).collect();
+
+        // Writes of odd sizes that cross block boundaries
+        let mut writer = CfbEncryptWriter::new(gost(), 0xBADF00D, Vec::new());
+        for piece in plain.chunks(3) {
+            writer.write_all(piece).unwrap();
+        }
+        let encrypted = writer.finish().unwrap();
+
+        assert_eq!(encrypted, expected[..plain.len()]);
+    }
+
+    #[test]
+    fn reader_reverses_writer() {
+        let plain: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let cipher = Kuznyechik::new(&[7; 32]);
+
+        let mut writer = CfbEncryptWriter::new(cipher.clone(), 42, Vec::new());
+        writer.write_all(&plain).unwrap();
+        let encrypted = writer.finish().unwrap();
+        assert_ne!(encrypted[..16], plain[..16]);
+
+        let mut reader = CfbDecryptReader::new(cipher, 42, encrypted.as_slice());
+        let mut decrypted = Vec::new();
+        let mut piece = [0; 7];
+        loop {
+            let read = reader.read(&mut piece).unwrap();
+            if read == 0 {
+                break;
+            }
+            decrypted.extend_from_slice(&piece[..read]);
+        }
+
+        assert_eq!(decrypted, plain);
+    }
+}
@@ -1,26 +1,177 @@
+use super::modes::*;
 use super::simple_swap::*;
 
-pub fn cipher_feedback_mode_block(init: u64, input: &mut [u64], key: [u32; 8]) -> u64 {
-    let mut last_encrypted: u64 = init;
+pub fn cipher_feedback_mode_block(
+    init: u64,
+    input: &mut [u64],
+    key: &MagmaKey,
+    params: &Params,
+) -> u64 {
+    cipher_feedback(&Gost28147::with_params(key.clone(), *params), init, input)
+}
 
-    input.iter_mut().for_each(|block| {
-        last_encrypted = *block ^ simple_swap(last_encrypted, key);
-        *block = last_encrypted;
+pub fn cipher_feedback_mode_block_decrypt(
+    init: u64,
+    input: &mut [u64],
+    key: &MagmaKey,
+    params: &Params,
+) -> u64 {
+    cipher_feedback_decrypt(&Gost28147::with_params(key.clone(), *params), init, input)
+}
+
+fn cipher_feedback_mode_segments_core(
+    register: &mut [u8],
+    segment: usize,
+    input: &mut [u8],
+    key: &MagmaKey,
+    params: &Params,
+    decrypt: bool,
+) {
+    assert!(
+        register.len() >= 8 && register.len().is_multiple_of(8),
+        "register must be whole blocks"
+    );
+    assert!((1..=8).contains(&segment), "segment must fit in a block");
+
+    // The register shifts towards the highest bits of the blocks as numbers and the
+    // segments are numbers xored with the highest bytes of the gamma, so with
+    // little-endian blocks the bytes are turned around while working on them
+    let little = params.order == ByteOrder::Little;
+    let in_numeric_order = |bytes: &mut [u8], chunk: usize| {
+        if little {
+            bytes.chunks_mut(chunk).for_each(<[u8]>::reverse);
+        }
+    };
+
+    in_numeric_order(register, 8);
+
+    input.chunks_mut(segment).for_each(|chunk| {
+        in_numeric_order(chunk, segment);
+
+        let head = u64::from_be_bytes(register[..8].try_into().unwrap());
+        let gamma = simple_swap_with(head, key, params).to_be_bytes();
+
+        register.rotate_left(chunk.len());
+        let fed_back = register.len() - chunk.len();
+
+        chunk
+            .iter_mut()
+            .zip(gamma)
+            .zip(&mut register[fed_back..])
+            .for_each(|((byte, gamma), fed_back)| {
+                let encrypted = if decrypt { *byte } else { *byte ^ gamma };
+                *byte ^= gamma;
+                *fed_back = encrypted;
+            });
+
+        in_numeric_order(chunk, segment);
     });
 
-    last_encrypted
+    in_numeric_order(register, 8);
+}
+
+/// Cipher feedback of GOST R 34.13-2015 with `segment` bytes of feedback
+/// and a register of `register.len()` bytes, a multiple of the block.
+/// Blocks of the register and segments of `input` are packed in `params.order`.
+/// To continue in the next call, `input` must be a multiple of `segment`.
+pub fn cipher_feedback_mode_segments(
+    register: &mut [u8],
+    segment: usize,
+    input: &mut [u8],
+    key: &MagmaKey,
+    params: &Params,
+) {
+    cipher_feedback_mode_segments_core(register, segment, input, key, params, false)
+}
+
+pub fn cipher_feedback_mode_segments_decrypt(
+    register: &mut [u8],
+    segment: usize,
+    input: &mut [u8],
+    key: &MagmaKey,
+    params: &Params,
+) {
+    cipher_feedback_mode_segments_core(register, segment, input, key, params, true)
 }
 
-pub fn cipher_feedback_mode_block_decrypt(init: u64, input: &mut [u64], key: [u32; 8]) -> u64 {
-    let mut last = init;
+/// Bytes processed with a key before CryptoPro key meshing changes it
+pub const KEY_MESHING_INTERVAL: usize = 1024;
+
+const KEY_MESHING_CONSTANT: [u8; 32] = [
+    0x69, 0x00, 0x72, 0x22, 0x64, 0xc9, 0x04, 0x23, 0x8d, 0x3a, 0xdb, 0x96, 0x46, 0xe9, 0x2a, 0xc4,
+    0x18, 0xfe, 0xac, 0x94, 0x00, 0xed, 0x07, 0x12, 0xc0, 0x86, 0xdc, 0xc2, 0xef, 0x4c, 0xa9, 0x2b,
+];
+
+/// CryptoPro key meshing of RFC 4357 section 2.3: the next key is
+/// the constant decrypted with the current one, in little-endian byte order
+pub fn key_meshing(key: &MagmaKey, params: &Params) -> MagmaKey {
+    let mut next = [0; 8];
+
+    KEY_MESHING_CONSTANT
+        .chunks_exact(8)
+        .zip(next.chunks_exact_mut(2))
+        .for_each(|(constant, words)| {
+            let block = u64::from_le_bytes(constant.try_into().unwrap());
+            let block = simple_swap_decrypt_with(block, key, params);
+            words[0] = block as u32;
+            words[1] = (block >> 32) as u32;
+        });
+
+    MagmaKey::new(next)
+}
 
+/// Key and feedback of the CFB mode with CryptoPro key meshing
+#[derive(Debug, Clone)]
+pub struct MeshedCfb {
+    key: MagmaKey,
+    register: u64,
+    processed: usize,
+}
+
+impl MeshedCfb {
+    pub fn new(key: MagmaKey, init: u64) -> Self {
+        Self {
+            key,
+            register: init,
+            processed: 0,
+        }
+    }
+
+    /// Meshes the key every `KEY_MESHING_INTERVAL` bytes and returns the next gamma
+    fn gamma(&mut self, params: &Params) -> u64 {
+        if self.processed == KEY_MESHING_INTERVAL {
+            self.key = key_meshing(&self.key, params);
+            self.register = simple_swap_with(self.register, &self.key, params);
+            self.processed = 0;
+        }
+
+        self.processed += 8;
+        simple_swap_with(self.register, &self.key, params)
+    }
+}
+
+/// Cipher feedback with the key meshed every 1024 bytes, `state` continues between calls
+pub fn cipher_feedback_mode_block_meshed(
+    state: &mut MeshedCfb,
+    input: &mut [u64],
+    params: &Params,
+) {
     input.iter_mut().for_each(|block| {
-        let open = *block ^ simple_swap(last, key);
-        last = *block;
-        *block = open;
+        *block ^= state.gamma(params);
+        state.register = *block;
     });
+}
 
-    last
+pub fn cipher_feedback_mode_block_meshed_decrypt(
+    state: &mut MeshedCfb,
+    input: &mut [u64],
+    params: &Params,
+) {
+    input.iter_mut().for_each(|block| {
+        let gamma = state.gamma(params);
+        state.register = *block;
+        *block ^= gamma;
+    });
 }
 
 #[cfg(test)]
@@ -29,14 +180,214 @@ mod tests {
 
     #[test]
     fn cfm_reversible() {
-        let key = [83, 3, 6, 24, 525, 646, 233, 32];
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
         let init = 0xBADF00D;
 
         let initial = [42, 45, 38];
+        for layout in [Layout::Standard, Layout::Legacy] {
+            let params = Params {
+                layout,
+                ..Default::default()
+            };
+            let mut copy = initial;
+
+            cipher_feedback_mode_block(init, &mut copy, &key, &params);
+            cipher_feedback_mode_block_decrypt(init, &mut copy, &key, &params);
+            assert_eq!(initial, copy);
+        }
+    }
+
+    fn register_bytes(blocks: &[u64]) -> Vec<u8> {
+        blocks.iter().flat_map(|b| b.to_le_bytes()).collect()
+    }
+
+    fn gost_34_13_key() -> MagmaKey {
+        MagmaKey::new([
+            0xffeeddcc, 0xbbaa9988, 0x77665544, 0x33221100, 0xf0f1f2f3, 0xf4f5f6f7, 0xf8f9fafb,
+            0xfcfdfeff,
+        ])
+    }
+
+    #[test]
+    fn segments_same_as_blocks() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let params = Params::default();
+        let init = 0xBADF00D;
+
+        let mut blocks = [42, 45, 38];
+        let mut bytes = register_bytes(&blocks);
+
+        cipher_feedback_mode_block(init, &mut blocks, &key, &params);
+        cipher_feedback_mode_segments(&mut init.to_le_bytes(), 8, &mut bytes, &key, &params);
+
+        assert_eq!(bytes, register_bytes(&blocks));
+    }
+
+    #[test]
+    fn segments_reversible() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let params = Params::default();
+        let register = register_bytes(&[0xBADF00D, 0xC0FFEE]);
+
+        let initial: Vec<u8> = (0..21).collect();
         let mut copy = initial.clone();
 
-        cipher_feedback_mode_block(init, &mut copy, key);
-        cipher_feedback_mode_block_decrypt(init, &mut copy, key);
-        assert_eq!(initial, copy);
+        for segment in 1..=8 {
+            cipher_feedback_mode_segments(&mut register.clone(), segment, &mut copy, &key, &params);
+            assert_ne!(initial, copy);
+            cipher_feedback_mode_segments_decrypt(
+                &mut register.clone(),
+                segment,
+                &mut copy,
+                &key,
+                &params,
+            );
+            assert_eq!(initial, copy);
+        }
+    }
+
+    #[test]
+    fn gost_34_13_vector() {
+        // GOST R 34.13-2015 appendix A.2.5, s = n, m = 2n
+        let params = Params::new(SBoxSet::TC26_Z);
+        let mut register = register_bytes(&[0x1234567890abcdef, 0x234567890abcdef1]);
+
+        let mut data = register_bytes(&[
+            0x92def06b3c130a59,
+            0xdb54c704f8189d20,
+            0x4a98fb2e67a8024c,
+            0x8912409b17b57e41,
+        ]);
+
+        cipher_feedback_mode_segments(&mut register, 8, &mut data, &gost_34_13_key(), &params);
+
+        let expected = register_bytes(&[
+            0xdb37e0e266903c83,
+            0x0d46644c1f9a089c,
+            0x24bdd2035315d38b,
+            0xbcc0321421075505,
+        ]);
+        assert_eq!(data, expected);
+    }
+
+    /// CFB of GOST R 34.13-2015 on numbers: the segments are the highest bits of the gamma
+    /// and the register of two blocks shifts them in from the lowest end
+    fn numeric_cfb(
+        register: [u64; 2],
+        segments: &[(u64, usize)],
+        gamma: impl Fn(u64) -> u64,
+    ) -> Vec<u64> {
+        let mut register = (register[0] as u128) << 64 | register[1] as u128;
+
+        segments
+            .iter()
+            .map(|&(segment, len)| {
+                let bits = len as u32 * 8;
+                let encrypted = segment ^ gamma((register >> 64) as u64) >> (64 - bits);
+                register = register << bits | encrypted as u128;
+                encrypted
+            })
+            .collect()
+    }
+
+    #[test]
+    fn short_segments_in_numeric_order() {
+        let key = gost_34_13_key();
+        let register = [0x1234567890abcdef, 0x234567890abcdef1];
+
+        for params in [Params::new(SBoxSet::TC26_Z), crate::Magma::PARAMS] {
+            let order = params.order;
+            let to_number = |bytes: &[u8]| {
+                let mut block = [0; 8];
+                match order {
+                    ByteOrder::Little => block[..bytes.len()].copy_from_slice(bytes),
+                    ByteOrder::Big => block[8 - bytes.len()..].copy_from_slice(bytes),
+                }
+                order.block(block)
+            };
+
+            for segment in 1..8 {
+                let plain: Vec<u8> = (0..segment as u8 * 3 + 2).collect();
+
+                let mut data = plain.clone();
+                let mut bytes: Vec<u8> = register.iter().flat_map(|&b| order.bytes(b)).collect();
+                let (head, tail) = data.split_at_mut(segment * 2);
+                cipher_feedback_mode_segments(&mut bytes, segment, head, &key, &params);
+                cipher_feedback_mode_segments(&mut bytes, segment, tail, &key, &params);
+
+                let segments: Vec<_> = plain
+                    .chunks(segment)
+                    .map(|chunk| (to_number(chunk), chunk.len()))
+                    .collect();
+                let expected = numeric_cfb(register, &segments, |block| {
+                    simple_swap_with(block, &key, &params)
+                });
+
+                let encrypted: Vec<_> = data.chunks(segment).map(to_number).collect();
+                assert_eq!(encrypted, expected, "{order:?}, {segment} bytes");
+            }
+        }
+    }
+
+    fn test_key() -> MagmaKey {
+        MagmaKey::new(std::array::from_fn(|i| {
+            let i = i as u8 * 4;
+            u32::from_le_bytes([i, i + 1, i + 2, i + 3])
+        }))
+    }
+
+    #[test]
+    fn key_meshing_known_answer() {
+        let params = Params::new(SBoxSet::CRYPTO_PRO_A);
+
+        assert_eq!(
+            key_meshing(&test_key(), &params).words(),
+            [
+                0x398c0b39, 0x1c67322f, 0x3030d2c2, 0xae5bf2b8, 0x1a7809f8, 0x42054091, 0x908b838c,
+                0x9e30db38
+            ]
+        );
+    }
+
+    #[test]
+    fn meshed_known_answer() {
+        let params = Params::new(SBoxSet::CRYPTO_PRO_A);
+        let init = 0x0807060504030201;
+
+        let plain: Vec<u64> = (0..263)
+            .map(|i| u64::from_le_bytes(std::array::from_fn(|j| ((i * 8 + j) % 251) as u8)))
+            .collect();
+
+        let mut blocks = plain.clone();
+        let mut state = MeshedCfb::new(test_key(), init);
+        let (head, tail) = blocks.split_at_mut(100);
+        cipher_feedback_mode_block_meshed(&mut state, head, &params);
+        cipher_feedback_mode_block_meshed(&mut state, tail, &params);
+
+        // Before and after the first meshing
+        assert_eq!(
+            register_bytes(&blocks[127..129]),
+            [
+                0x80, 0x5b, 0x8b, 0x3e, 0x12, 0xd6, 0x52, 0x51, 0xea, 0x90, 0x96, 0x3a, 0x10, 0x39,
+                0xb1, 0xd9
+            ]
+        );
+        assert_eq!(
+            register_bytes(&blocks[255..257]),
+            [
+                0x31, 0x00, 0xe5, 0x55, 0x85, 0x27, 0x99, 0xb4, 0xed, 0x5e, 0x39, 0xa1, 0xa3, 0xb9,
+                0xda, 0xc2
+            ]
+        );
+
+        // The first 1024 bytes are plain CFB
+        let mut unmeshed = plain.clone();
+        cipher_feedback_mode_block(init, &mut unmeshed, &test_key(), &params);
+        assert_eq!(blocks[..128], unmeshed[..128]);
+        assert_ne!(blocks[128..], unmeshed[128..]);
+
+        let mut state = MeshedCfb::new(test_key(), init);
+        cipher_feedback_mode_block_meshed_decrypt(&mut state, &mut blocks, &params);
+        assert_eq!(blocks, plain);
     }
 }
@@ -0,0 +1,141 @@
+use super::simple_swap::*;
+
+const C1: u32 = 0x1010104;
+const C2: u32 = 0x1010101;
+
+/// Encrypts the IV once, giving the counter register the gamma is generated from
+pub fn counter_mode_init(iv: u64, key: &MagmaKey, params: &Params) -> u64 {
+    simple_swap_with(iv, key, params)
+}
+
+/// Adds C2 modulo 2^32 to N3 and C1 modulo 2^32 - 1 to N4
+fn next_counter(counter: u64) -> u64 {
+    let n3 = (counter as u32).wrapping_add(C2);
+
+    let (n4, overflowed) = ((counter >> 32) as u32).overflowing_add(C1);
+    let n4 = n4 + overflowed as u32;
+
+    ((n4 as u64) << 32) | n3 as u64
+}
+
+/// The counter `blocks` steps later, so a part of the gamma can start at once
+pub fn counter_mode_skip(counter: u64, blocks: u64) -> u64 {
+    const N4_MODULUS: u64 = u32::MAX as u64;
+
+    if blocks == 0 {
+        return counter;
+    }
+
+    let n3 = (counter as u32).wrapping_add(C2.wrapping_mul(blocks as u32));
+
+    // After a step N4 is never zero, 2^32 - 1 stands for it
+    let n4 = ((counter >> 32) % N4_MODULUS + C1 as u64 * (blocks % N4_MODULUS)) % N4_MODULUS;
+    let n4 = if n4 == 0 { N4_MODULUS } else { n4 };
+
+    (n4 << 32) | n3 as u64
+}
+
+/// Gamming: xors every block with the encrypted next counter value.
+/// Encryption and decryption are the same operation.
+pub fn counter_mode_block(counter: u64, input: &mut [u64], key: &MagmaKey, params: &Params) -> u64 {
+    let mut counter = counter;
+    let mut gamma = [0; BITSLICE_LANES];
+
+    input.chunks_mut(BITSLICE_LANES).for_each(|chunk| {
+        let gamma = &mut gamma[..chunk.len()];
+        gamma.iter_mut().for_each(|gamma| {
+            counter = next_counter(counter);
+            *gamma = counter;
+        });
+
+        simple_swap_blocks_with(gamma, key, params);
+        chunk
+            .iter_mut()
+            .zip(gamma)
+            .for_each(|(block, gamma)| *block ^= *gamma);
+    });
+
+    counter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_reversible() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let params = Params::default();
+        let counter = counter_mode_init(0xBADF00D, &key, &params);
+
+        let initial = [42, 45, 38];
+        let mut copy = initial;
+
+        counter_mode_block(counter, &mut copy, &key, &params);
+        assert_ne!(initial, copy);
+        counter_mode_block(counter, &mut copy, &key, &params);
+        assert_eq!(initial, copy);
+    }
+
+    #[test]
+    fn counter_known_answer() {
+        let key = MagmaKey::new(std::array::from_fn(|i| {
+            let i = i as u8 * 4;
+            u32::from_le_bytes([i, i + 1, i + 2, i + 3])
+        }));
+        let params = Params::default();
+        let counter = counter_mode_init(0x0807060504030201, &key, &params);
+
+        // "GOST 28147-89 counter mo"
+        let mut blocks = [0x3138322054534f47, 0x6f632039382d3734, 0x6f6d207265746e75];
+
+        counter_mode_block(counter, &mut blocks, &key, &params);
+        assert_eq!(
+            blocks,
+            [0x8298af76a5663ab0, 0x2f9034374e8b5b8c, 0x13cf931dff85c3ae]
+        );
+    }
+
+    #[test]
+    fn split_buffers() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let params = Params::default();
+        let counter = counter_mode_init(0xBADF00D, &key, &params);
+
+        let mut whole = [1, 2, 3, 4, 5];
+        counter_mode_block(counter, &mut whole, &key, &params);
+
+        let mut parts = [1, 2, 3, 4, 5];
+        let (head, tail) = parts.split_at_mut(2);
+        let next = counter_mode_block(counter, head, &key, &params);
+        counter_mode_block(next, tail, &key, &params);
+
+        assert_eq!(whole, parts);
+    }
+
+    #[test]
+    fn skip_same_as_steps() {
+        for start in [
+            0,
+            0xBADF00D,
+            u64::MAX,
+            0xFFFFFFFE_FFFFFFFF,
+            0xFEFEFEFC_00000000,
+        ] {
+            let mut counter = start;
+            for blocks in 0..600 {
+                assert_eq!(counter_mode_skip(start, blocks), counter);
+                counter = next_counter(counter);
+            }
+        }
+    }
+
+    #[test]
+    fn n4_wraps_modulo_2_32_minus_1() {
+        let counter = (0xFFFFFFFE_u64 << 32) | 0xFFFFFFFF;
+        assert_eq!(
+            next_counter(counter),
+            ((C1 as u64 - 1) << 32) | (C2 as u64 - 1)
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::block_cipher::*;
+use super::ctr::*;
+use super::modes::counter;
+use super::simple_swap::*;
+
+/// The gamma of a counter mode, any block of it can be computed on its own
+pub trait Gamma {
+    type Block: CipherBlock;
+
+    /// Xors `blocks` with the gamma from block `index` on
+    fn apply_at(&self, index: u64, blocks: &mut [Self::Block]);
+
+    /// How the blocks of the gamma are packed into bytes
+    fn byte_order(&self) -> ByteOrder;
+}
+
+/// The counter mode of GOST R 34.13-2015: block `i` of the gamma is
+/// the encrypted `counter + i`
+#[derive(Debug, Clone)]
+pub struct CounterGamma<C: BlockCipher> {
+    cipher: C,
+    counter: C::Block,
+}
+
+impl<C: BlockCipher> CounterGamma<C> {
+    /// `counter` is the counter of the first block
+    pub fn new(cipher: C, counter: C::Block) -> Self {
+        Self { cipher, counter }
+    }
+}
+
+impl<C: BlockCipher> Gamma for CounterGamma<C> {
+    type Block = C::Block;
+
+    fn apply_at(&self, index: u64, blocks: &mut [C::Block]) {
+        counter(&self.cipher, self.counter.advance(index), blocks);
+    }
+
+    fn byte_order(&self) -> ByteOrder {
+        self.cipher.byte_order()
+    }
+}
+
+/// Gamming of GOST 28147-89, `counter_mode_block` from the IV
+#[derive(Debug, Clone)]
+pub struct Gost28147Gamma {
+    key: MagmaKey,
+    params: Params,
+    counter: u64,
+}
+
+impl Gost28147Gamma {
+    pub fn new(key: MagmaKey, iv: u64, params: Params) -> Self {
+        Self {
+            counter: counter_mode_init(iv, &key, &params),
+            key,
+            params,
+        }
+    }
+}
+
+impl Gamma for Gost28147Gamma {
+    type Block = u64;
+
+    fn apply_at(&self, index: u64, blocks: &mut [u64]) {
+        let counter = counter_mode_skip(self.counter, index);
+        counter_mode_block(counter, blocks, &self.key, &self.params);
+    }
+
+    fn byte_order(&self) -> ByteOrder {
+        self.params.order
+    }
+}
+
+/// Reads a counter mode ciphertext from `inner` and decrypts it on the fly.
+///
+/// Seeking goes straight to the gamma of the new position, so a part of
+/// a large file is decrypted without the ones before it. Positions are
+/// counted in the plaintext, the ciphertext starts where `inner` was
+/// when the reader was made.
+#[derive(Debug)]
+pub struct CtrDecryptReader<G: Gamma, R: Read + Seek> {
+    gamma: G,
+    inner: R,
+    /// Position of the ciphertext in `inner`
+    start: u64,
+    position: u64,
+    blocks: Vec<G::Block>,
+    bytes: Vec<u8>,
+}
+
+impl<G: Gamma, R: Read + Seek> CtrDecryptReader<G, R> {
+    pub fn new(gamma: G, mut inner: R) -> io::Result<Self> {
+        Ok(Self {
+            gamma,
+            start: inner.stream_position()?,
+            inner,
+            position: 0,
+            blocks: Vec::new(),
+            bytes: Vec::new(),
+        })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Xors `data` at the current position with the gamma
+    fn apply(&mut self, data: &mut [u8]) {
+        let size = G::Block::SIZE;
+        let skip = (self.position % size as u64) as usize;
+        let count = (skip + data.len()).div_ceil(size);
+
+        // The gamma itself is the encrypted zero blocks
+        self.blocks.clear();
+        self.blocks.resize(count, G::Block::default());
+        self.gamma
+            .apply_at(self.position / size as u64, &mut self.blocks);
+
+        self.bytes.resize(count * size, 0);
+        let order = self.gamma.byte_order();
+        self.blocks
+            .iter()
+            .zip(self.bytes.chunks_exact_mut(size))
+            .for_each(|(block, bytes)| block.write_bytes(bytes, order));
+
+        data.iter_mut()
+            .zip(&self.bytes[skip..])
+            .for_each(|(byte, gamma)| *byte ^= gamma);
+    }
+}
+
+impl<G: Gamma, R: Read + Seek> Read for CtrDecryptReader<G, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.apply(&mut buf[..len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<G: Gamma, R: Read + Seek> Seek for CtrDecryptReader<G, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => {
+                let offset = self.start.checked_add(offset).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "position is too large")
+                })?;
+                SeekFrom::Start(offset)
+            }
+            pos => pos,
+        };
+
+        let current = self.inner.stream_position()?;
+        let position = self.inner.seek(pos)?;
+
+        if position < self.start {
+            self.inner.seek(SeekFrom::Start(current))?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't seek before the start of the ciphertext",
+            ));
+        }
+
+        self.position = position - self.start;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::Kuznyechik;
+
+    #[test]
+    fn gost28147_same_as_counter_mode() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let params = Params::default();
+        let plain: Vec<u8> = (0..=255).cycle().take(1000).collect();
+
+        let mut blocks: Vec<u64> = plain
+            .chunks(8)
+            .map(|c| u64::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        let counter = counter_mode_init(0xBADF00D, &key, &params);
+        counter_mode_block(counter, &mut blocks, &key, &params);
+        let encrypted: Vec<u8> = blocks.iter().flat_map(|b| b.to_le_bytes()).collect();
+
+        // A header of odd length before the ciphertext
+        let mut file = vec![7; 5];
+        file.extend_from_slice(&encrypted);
+        let mut inner = Cursor::new(file);
+        inner.set_position(5);
+
+        let gamma = Gost28147Gamma::new(key, 0xBADF00D, params);
+        let mut reader = CtrDecryptReader::new(gamma, inner).unwrap();
+
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, plain);
+
+        for (offset, len) in [(0, 3), (13, 50), (997, 3), (64, 64), (500, 1)] {
+            let mut slice = vec![0; len];
+            assert_eq!(reader.seek(SeekFrom::Start(offset)).unwrap(), offset);
+            reader.read_exact(&mut slice).unwrap();
+            assert_eq!(slice, plain[offset as usize..offset as usize + len]);
+        }
+
+        assert_eq!(reader.seek(SeekFrom::End(-10)).unwrap(), 990);
+        assert_eq!(reader.seek(SeekFrom::Current(-985)).unwrap(), 5);
+        assert!(reader.seek(SeekFrom::Current(-6)).is_err());
+        assert_eq!(reader.stream_position().unwrap(), 5);
+    }
+
+    #[test]
+    fn kuznyechik_same_as_counter() {
+        let cipher = Kuznyechik::new(&[7; 32]);
+        let plain: Vec<u8> = (0..100).collect();
+
+        let mut blocks = vec![0u128; 7];
+        blocks
+            .iter_mut()
+            .zip(plain.chunks(16))
+            .for_each(|(block, c)| {
+                let mut bytes = [0; 16];
+                bytes[..c.len()].copy_from_slice(c);
+                *block = u128::from_be_bytes(bytes);
+            });
+        counter(&cipher, 42 << 64, &mut blocks);
+        let encrypted: Vec<u8> = blocks.iter().flat_map(|b| b.to_be_bytes()).collect();
+
+        let gamma = CounterGamma::new(cipher, 42 << 64);
+        let mut reader =
+            CtrDecryptReader::new(gamma, Cursor::new(&encrypted[..plain.len()])).unwrap();
+
+        let mut tail = Vec::new();
+        reader.seek(SeekFrom::Start(37)).unwrap();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, plain[37..]);
+    }
+}
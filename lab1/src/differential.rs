@@ -0,0 +1,478 @@
+//! Differential characteristics of reduced-round ciphers. XOR differences go
+//! through the S-boxes with the probabilities of their DDTs and through additions
+//! modulo 2^32 unchanged, with the probability of Lipmaa and Moriai. Weights are
+//! `-log2` of probabilities, so the weight of a trail is the sum of its steps.
+
+use std::collections::BTreeMap;
+
+use rand::distributions::{Distribution, Standard};
+use rand::Rng;
+
+use crate::simple_swap::{join_block, split_block};
+use crate::{CipherBlock, Params, SBoxError, SBoxReport};
+
+/// Outputs kept for a layer of S-boxes
+pub const SUBSTITUTION_BRANCHES: usize = 16;
+
+/// Weight of the XOR difference of `x + y` being `alpha ^ beta`
+/// when those of `x` and `y` are `alpha` and `beta`
+pub fn addition_weight(alpha: u32, beta: u32) -> f64 {
+    ((alpha | beta) & 0x7fff_ffff).count_ones() as f64
+}
+
+/// Output differences of an S-box for every input difference, the most likely first
+#[derive(Debug, Clone)]
+pub struct Transitions {
+    outputs: Vec<Vec<(u8, f64)>>,
+}
+
+impl Transitions {
+    pub fn new(sbox: &[u8]) -> Result<Self, SBoxError> {
+        let size = sbox.len() as f64;
+
+        let outputs = SBoxReport::new(sbox)?
+            .ddt
+            .iter()
+            .map(|row| {
+                let mut outputs: Vec<_> = (0..row.len())
+                    .filter(|&b| row[b] > 0)
+                    .map(|b| (b as u8, (size / row[b] as f64).log2()))
+                    .collect();
+                outputs.sort_by(|x, y| x.1.total_cmp(&y.1));
+                outputs
+            })
+            .collect();
+
+        Ok(Self { outputs })
+    }
+
+    /// Output differences for the input difference `a` with their weights
+    pub fn outputs(&self, a: u8) -> &[(u8, f64)] {
+        &self.outputs[a as usize]
+    }
+}
+
+/// The `limit` lightest outputs of S-boxes side by side,
+/// `inputs[i]` goes through `transitions[i]`
+pub fn substitution_outputs(
+    inputs: &[u8],
+    transitions: &[&Transitions],
+    limit: usize,
+) -> Vec<(Vec<u8>, f64)> {
+    let mut partial = vec![(Vec::with_capacity(inputs.len()), 0.0)];
+
+    for (&input, transitions) in inputs.iter().zip(transitions) {
+        // The partial outputs to extend and how, only the kept ones are copied
+        let mut next: Vec<_> = partial
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (_, weight))| {
+                transitions
+                    .outputs(input)
+                    .iter()
+                    .take(limit)
+                    .map(move |&(output, step)| (i, output, weight + step))
+            })
+            .collect();
+
+        next.sort_by(|x, y| x.2.total_cmp(&y.2));
+        next.truncate(limit);
+
+        partial = next
+            .into_iter()
+            .map(|(i, output, weight)| {
+                let mut outputs = partial[i].0.clone();
+                outputs.push(output);
+                (outputs, weight)
+            })
+            .collect();
+    }
+
+    partial
+}
+
+/// Keeps the `limit` lightest of the states, the lightest way to each of them.
+/// Ties go to the smaller state, so searches are repeatable
+pub fn lightest<S: Copy + Ord, P>(states: Vec<(S, P, f64)>, limit: usize) -> Vec<(S, P, f64)> {
+    let mut best: BTreeMap<S, (P, f64)> = BTreeMap::new();
+
+    for (state, path, weight) in states {
+        match best.get(&state) {
+            Some(&(_, known)) if known <= weight => {}
+            _ => {
+                best.insert(state, (path, weight));
+            }
+        }
+    }
+
+    let mut states: Vec<_> = best
+        .into_iter()
+        .map(|(state, (path, weight))| (state, path, weight))
+        .collect();
+    states.sort_by(|x, y| x.2.total_cmp(&y.2));
+    states.truncate(limit);
+    states
+}
+
+/// Beam search of trails over `rounds` rounds: keeps the `width` lightest states
+/// after every round, `round` gives the states a state can lead to with the weights.
+/// Returns the states before the first round and after every round of the trails
+/// left in the beam, the lightest first.
+pub fn beam_search<S: Copy + Ord>(
+    starts: impl IntoIterator<Item = S>,
+    rounds: usize,
+    width: usize,
+    round: impl Fn(S) -> Vec<(S, f64)>,
+) -> Vec<(Vec<S>, f64)> {
+    let mut beam: Vec<(S, Vec<S>, f64)> = starts
+        .into_iter()
+        .map(|start| (start, vec![start], 0.0))
+        .collect();
+
+    for _ in 0..rounds {
+        let next = beam
+            .iter()
+            .flat_map(|(state, path, weight)| {
+                round(*state).into_iter().map(move |(next, step)| {
+                    let mut path = path.clone();
+                    path.push(next);
+                    (next, path, weight + step)
+                })
+            })
+            .collect();
+
+        beam = lightest(next, width);
+    }
+
+    beam.into_iter()
+        .map(|(_, path, weight)| (path, weight))
+        .collect()
+}
+
+/// A differential characteristic
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trail<B> {
+    /// The input difference and the output differences of the cipher
+    /// reduced to 1, 2, ... rounds
+    pub differences: Vec<B>,
+    /// `-log2` of the probability of the trail
+    pub weight: f64,
+}
+
+impl<B: Copy> Trail<B> {
+    pub fn rounds(&self) -> usize {
+        self.differences.len() - 1
+    }
+
+    pub fn input(&self) -> B {
+        self.differences[0]
+    }
+
+    pub fn output(&self) -> B {
+        *self.differences.last().unwrap()
+    }
+}
+
+/// Pairs of inputs drawn from `rng` with the input difference of the trail
+/// whose outputs under `encrypt` have its output difference
+pub fn right_pairs<B: CipherBlock>(
+    trail: &Trail<B>,
+    pairs: usize,
+    rng: &mut impl Rng,
+    encrypt: impl Fn(B) -> B,
+) -> usize
+where
+    Standard: Distribution<B>,
+{
+    (0..pairs)
+        .filter(|_| {
+            let x: B = rng.gen();
+            encrypt(x) ^ encrypt(x ^ trail.input()) == trail.output()
+        })
+        .count()
+}
+
+/// Differences of the GOST 28147-89 round function output for the input difference `a`
+fn gost28147_round_outputs(a: u32, transitions: &[&Transitions]) -> Vec<(u32, f64)> {
+    if a == 0 {
+        return vec![(0, 0.0)];
+    }
+
+    let nibbles: Vec<u8> = (0..8).map(|n| (a >> (n * 4) & 0xf) as u8).collect();
+
+    substitution_outputs(&nibbles, transitions, SUBSTITUTION_BRANCHES)
+        .into_iter()
+        .map(|(outputs, weight)| {
+            let substituted = outputs
+                .iter()
+                .rev()
+                .fold(0, |word, &nibble| word << 4 | nibble as u32);
+
+            (substituted.rotate_left(11), weight + addition_weight(a, 0))
+        })
+        .collect()
+}
+
+/// Searches for light trails over `rounds` rounds of `simple_swap_rounds`,
+/// starting from every difference in a single nibble. Returns up to `width`
+/// trails, the lightest first.
+///
+/// The weights are averages over the keys: a carry into a nibble depends
+/// on the subkey, so for a given key a trail may hold more often or never.
+pub fn gost28147_trails(params: &Params, rounds: usize, width: usize) -> Vec<Trail<u64>> {
+    let transitions = params
+        .nibble_tables()
+        .map(|table| Transitions::new(&table).unwrap());
+    let transitions: Vec<_> = transitions.iter().collect();
+
+    // The difference of the half going into the round function and of the other one
+    let starts =
+        (0..8).flat_map(|n| (1..16).flat_map(move |d| [(0, d << (n * 4)), (d << (n * 4), 0)]));
+
+    let trails = beam_search(starts, rounds, width, |(a, b): (u32, u32)| {
+        gost28147_round_outputs(a, &transitions)
+            .into_iter()
+            .map(|(f, weight)| ((b ^ f, a), weight))
+            .collect()
+    });
+
+    // The last round doesn't swap the halves, N1 is the half that went
+    // into the round function before it
+    trails
+        .into_iter()
+        .map(|(path, weight)| Trail {
+            differences: path
+                .iter()
+                .enumerate()
+                .map(|(i, &(a, b))| match i {
+                    0 => join_block(a, b, params.layout),
+                    _ => join_block(b, a, params.layout),
+                })
+                .collect(),
+            weight,
+        })
+        .collect()
+}
+
+/// What the last round attack found
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastRoundAttack {
+    /// The values of nibble `n` of the subkey the pairs agree with nearly as well
+    /// as with the best one. Empty for nibbles whose S-boxes aren't active in the
+    /// last round, the pairs tell nothing about them. A value and the one that swaps
+    /// the inputs of every pair can't be told apart.
+    pub candidates: [Vec<u8>; 8],
+    /// The trail the attack went with
+    pub trail: Trail<u64>,
+    /// Pairs whose output difference in N1 matched the trail
+    pub filtered: usize,
+}
+
+impl LastRoundAttack {
+    /// Bits of the subkey the attack found candidates for
+    pub fn recovered(&self) -> u32 {
+        (0..8)
+            .filter(|&n| !self.candidates[n].is_empty())
+            .fold(0, |mask, n| mask | 0xf << (n * 4))
+    }
+
+    /// Whether the recovered bits of `subkey` are among the candidates
+    pub fn matches(&self, subkey: u32) -> bool {
+        self.candidates.iter().enumerate().all(|(n, candidates)| {
+            candidates.is_empty() || candidates.contains(&((subkey >> (n * 4) & 0xf) as u8))
+        })
+    }
+
+    /// Number of subkeys left to try
+    pub fn subkeys_left(&self) -> u64 {
+        self.candidates
+            .iter()
+            .map(|candidates| match candidates.len() {
+                0 => 16,
+                len => len as u64,
+            })
+            .product()
+    }
+}
+
+/// Pairs of chosen inputs with the input difference of the trail whose output
+/// difference in N1 matches its difference of the half going into the last round.
+/// Each is kept as the two N1 values and the difference of the S-box outputs
+/// of the last round the trail predicts.
+fn filtered_pairs(
+    trail: &Trail<u64>,
+    params: &Params,
+    pairs: usize,
+    rng: &mut impl Rng,
+    encrypt: impl Fn(u64) -> u64,
+) -> Vec<(u32, u32, u32)> {
+    // N2 went into the last round, N1 is xored with its output
+    let (before, into) = split_block(trail.output(), params.layout);
+
+    // Inactive S-boxes give no difference, so pairs with one there followed another trail
+    let active = (0..8)
+        .filter(|n| into >> (n * 4) & 0xf != 0)
+        .fold(0, |mask, n| mask | 0xf << (n * 4));
+
+    (0..pairs)
+        .filter_map(|_| {
+            let x: u64 = rng.gen();
+            let (n1, n2) = split_block(encrypt(x), params.layout);
+            let (other_n1, other_n2) = split_block(encrypt(x ^ trail.input()), params.layout);
+
+            let substituted = (n2 ^ other_n2 ^ before).rotate_right(11);
+            (n1 ^ other_n1 == into && substituted & !active == 0).then_some((
+                n1,
+                other_n1,
+                substituted,
+            ))
+        })
+        .collect()
+}
+
+/// Recovers the subkey of the last round of `simple_swap_rounds` reduced to one
+/// round more than the trails, `encrypt` is the cipher under attack and `rng`
+/// chooses the inputs.
+///
+/// Every trail gets `pairs` pairs of chosen inputs, the one most of them follow
+/// to the last round is taken, since whether a trail holds depends on the key.
+/// A nibble of the subkey is guessed together with the two below it, which
+/// give the carry into it unless a carry comes from further below. Guesses
+/// scoring within a quarter of the best one are kept.
+pub fn gost28147_last_round_attack(
+    trails: &[Trail<u64>],
+    params: &Params,
+    pairs: usize,
+    rng: &mut impl Rng,
+    encrypt: impl Fn(u64) -> u64,
+) -> Option<LastRoundAttack> {
+    assert!(
+        trails.iter().all(|trail| trail.rounds() > 0),
+        "the trails must cover a round"
+    );
+
+    let (trail, filtered) = trails
+        .iter()
+        .map(|trail| (trail, filtered_pairs(trail, params, pairs, rng, &encrypt)))
+        .max_by_key(|(_, filtered)| filtered.len())?;
+
+    let (_, into) = split_block(trail.output(), params.layout);
+    let mut candidates: [Vec<u8>; 8] = Default::default();
+
+    for (n, table) in params.nibble_tables().iter().enumerate() {
+        if into >> (n * 4) & 0xf == 0 {
+            continue;
+        }
+
+        // Bits from the two nibbles below, fewer for the lowest ones
+        let low = n.min(2) * 4;
+        let shift = n * 4 - low;
+
+        let score = |guess: u32| {
+            let nibble =
+                |x: u32| table[((x >> shift) % (1 << (low + 4)) + guess) as usize >> low & 0xf];
+
+            // Pairs that followed another trail to the same N1 difference are off
+            // from the prediction by the same amount, the largest group counts
+            let mut off = [0; 16];
+            for &(x, other, substituted) in &filtered {
+                off[((nibble(x) ^ nibble(other)) as u32 ^ substituted >> (n * 4) & 0xf)
+                    as usize] += 1;
+            }
+            off.into_iter().max().unwrap()
+        };
+
+        let scores: Vec<_> = (0..1 << (low + 4))
+            .map(|guess| (guess, score(guess)))
+            .collect();
+        let best = scores.iter().map(|&(_, score)| score).max().unwrap();
+
+        candidates[n] = scores
+            .iter()
+            .filter(|&&(_, score)| score * 4 >= best * 3)
+            .map(|&(guess, _)| (guess >> low) as u8)
+            .collect();
+        candidates[n].sort_unstable();
+        candidates[n].dedup();
+    }
+
+    Some(LastRoundAttack {
+        candidates,
+        trail: trail.clone(),
+        filtered: filtered.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{simple_swap_rounds, Backend, MagmaKey, SBoxSet};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// The keys are random, so the pairs go through the faster tables
+    fn table_params() -> Params {
+        Params {
+            backend: Backend::Table,
+            ..Params::new(SBoxSet::TC26_Z)
+        }
+    }
+
+    #[test]
+    fn transitions_sorted() {
+        let sbox = SBoxSet::TC26_Z.tables[0];
+        let transitions = Transitions::new(&sbox).unwrap();
+
+        assert_eq!(transitions.outputs(0), [(0, 0.0)]);
+
+        for a in 1..16 {
+            let outputs = transitions.outputs(a);
+            let total: f64 = outputs.iter().map(|(_, w)| (-w).exp2()).sum();
+            assert!((total - 1.0).abs() < 1e-9);
+            assert!(outputs.windows(2).all(|w| w[0].1 <= w[1].1));
+        }
+    }
+
+    #[test]
+    fn gost28147_trail_holds() {
+        let params = table_params();
+        let trail = &gost28147_trails(&params, 3, 64)[0];
+
+        assert_eq!(trail.rounds(), 3);
+
+        // On average over the keys the trail holds for the real cipher
+        // about as often as predicted
+        let mut rng = StdRng::seed_from_u64(1);
+        let (keys, pairs) = (32, 1 << 12);
+        let right: usize = (0..keys)
+            .map(|_| {
+                let key = MagmaKey::new(rng.gen());
+                right_pairs(trail, pairs, &mut rng, |x| {
+                    simple_swap_rounds(x, &key, 3, &params)
+                })
+            })
+            .sum();
+
+        let expected = (keys * pairs) as f64 * (-trail.weight).exp2();
+        assert!(right as f64 > expected / 4.0 && (right as f64) < expected * 4.0);
+    }
+
+    #[test]
+    fn recovers_last_subkey() {
+        let params = table_params();
+        let trails = gost28147_trails(&params, 3, 16);
+
+        let mut rng = StdRng::seed_from_u64(2);
+
+        for _ in 0..4 {
+            let key = MagmaKey::new(rng.gen());
+            let attack = gost28147_last_round_attack(&trails, &params, 1 << 12, &mut rng, |x| {
+                simple_swap_rounds(x, &key, 4, &params)
+            })
+            .unwrap();
+
+            assert_ne!(attack.recovered(), 0);
+            assert!(attack.matches(key.round_key(3)));
+            assert!(attack.subkeys_left() < 1 << 32);
+        }
+    }
+}
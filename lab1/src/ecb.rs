@@ -0,0 +1,32 @@
+use super::modes::*;
+use super::simple_swap::*;
+
+/// Simple replacement: every block is encrypted on its own
+pub fn simple_replacement_mode_block(input: &mut [u64], key: &MagmaKey, params: &Params) {
+    simple_replacement(&Gost28147::with_params(key.clone(), *params), input)
+}
+
+pub fn simple_replacement_mode_block_decrypt(input: &mut [u64], key: &MagmaKey, params: &Params) {
+    simple_replacement_decrypt(&Gost28147::with_params(key.clone(), *params), input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ecb_reversible() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let params = Params::default();
+
+        let initial = [42, 45, 42];
+        let mut copy = initial;
+
+        simple_replacement_mode_block(&mut copy, &key, &params);
+        assert_eq!(copy[0], copy[2]);
+        assert_eq!(copy[0], simple_swap(42, &key));
+
+        simple_replacement_mode_block_decrypt(&mut copy, &key, &params);
+        assert_eq!(initial, copy);
+    }
+}
@@ -0,0 +1,491 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use rand::{rngs::OsRng, RngCore};
+
+use super::key_file::KCV_LEN;
+use super::padding::Padding;
+use super::simple_swap::{Layout, SBoxSet};
+
+/// Magic bytes every header starts with
+pub const HEADER_MAGIC: [u8; 4] = *b"GCF\x1a";
+
+/// Version of the header layout
+pub const HEADER_VERSION: u8 = 1;
+
+pub const KDF_SALT_LEN: usize = 16;
+
+/// Most key derivation iterations a header may ask for, so a changed
+/// header can't keep the decryption busy for hours
+pub const MAX_KDF_ITERATIONS: u32 = 1_000_000;
+
+/// Plaintext length of a header written before the length was known,
+/// when encrypting a stream. The plaintext ends with the ciphertext then.
+pub const UNKNOWN_LEN: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherId {
+    Gost28147 = 1,
+    Magma = 2,
+    Kuznyechik = 3,
+    Belt = 4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeId {
+    Cfb = 1,
+    Ctr = 2,
+    Ecb = 3,
+    Cbc = 4,
+    Ofb = 5,
+    AcpkmCtr = 6,
+    Mgm = 7,
+}
+
+impl TryFrom<u8> for CipherId {
+    type Error = HeaderError;
+
+    fn try_from(value: u8) -> Result<Self, HeaderError> {
+        [Self::Gost28147, Self::Magma, Self::Kuznyechik, Self::Belt]
+            .into_iter()
+            .find(|&id| id as u8 == value)
+            .ok_or(HeaderError::UnknownCipher(value))
+    }
+}
+
+impl TryFrom<u8> for ModeId {
+    type Error = HeaderError;
+
+    fn try_from(value: u8) -> Result<Self, HeaderError> {
+        [
+            Self::Cfb,
+            Self::Ctr,
+            Self::Ecb,
+            Self::Cbc,
+            Self::Ofb,
+            Self::AcpkmCtr,
+            Self::Mgm,
+        ]
+        .into_iter()
+        .find(|&id| id as u8 == value)
+        .ok_or(HeaderError::UnknownMode(value))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The data doesn't start with `HEADER_MAGIC`
+    Magic,
+    Version(u8),
+    UnknownCipher(u8),
+    UnknownMode(u8),
+    /// An S-box set, a padding or flags this version doesn't know
+    InvalidOptions,
+    /// Zero or more than `MAX_KDF_ITERATIONS`
+    KdfIterations(u32),
+    /// The key derived from the passphrase has another check value
+    WrongPassphrase,
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Magic => f.write_str("no ciphertext header, the file may be of an older version"),
+            Self::Version(v) => write!(f, "unsupported header version {v}"),
+            Self::UnknownCipher(id) => write!(f, "unknown cipher {id} in the header"),
+            Self::UnknownMode(id) => write!(f, "unknown mode {id} in the header"),
+            Self::InvalidOptions => f.write_str("invalid cipher options in the header"),
+            Self::KdfIterations(n) => write!(
+                f,
+                "{n} key derivation iterations in the header, 1 to {MAX_KDF_ITERATIONS} are allowed"
+            ),
+            Self::WrongPassphrase => f.write_str("wrong passphrase"),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+impl From<HeaderError> for io::Error {
+    fn from(value: HeaderError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, value)
+    }
+}
+
+/// How the key was derived from a passphrase
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+    /// Key check value of the derived key, see `key_check_value`
+    pub check_value: [u8; KCV_LEN],
+}
+
+impl KdfParams {
+    /// Parameters with a fresh random salt, the check value is set
+    /// once the key is derived
+    pub fn random(iterations: u32) -> Self {
+        let mut salt = vec![0; KDF_SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+
+        Self {
+            salt,
+            iterations,
+            check_value: [0; KCV_LEN],
+        }
+    }
+
+    /// Tells a wrong passphrase by the check value of the key derived from it
+    pub fn verify(&self, check_value: [u8; KCV_LEN]) -> Result<(), HeaderError> {
+        if check_value != self.check_value {
+            return Err(HeaderError::WrongPassphrase);
+        }
+
+        Ok(())
+    }
+}
+
+const LEGACY_LAYOUT: u8 = 1;
+const KEY_MESHING: u8 = 2;
+
+/// Options of the cipher and the mode the ciphertext depends on,
+/// the ones a cipher or a mode doesn't have keep their defaults
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CipherOptions {
+    /// S-boxes of GOST 28147-89
+    pub sbox: SBoxSet,
+    pub layout: Layout,
+    /// Bytes fed back per step in cfb mode, a whole block when `None`
+    pub segment: Option<u8>,
+    pub padding: Padding,
+    pub key_meshing: bool,
+    /// Section size of ACPKM re-keying in bytes
+    pub section_size: u32,
+}
+
+impl CipherOptions {
+    /// Size of the options in the header
+    const SIZE: usize = 8;
+
+    fn write_to<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let sbox = SBoxSet::ALL.iter().position(|set| *set == self.sbox);
+        let sbox = sbox.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "the S-boxes have no name")
+        })?;
+        let padding = Padding::ALL
+            .iter()
+            .position(|&p| p == self.padding)
+            .unwrap();
+
+        let mut flags = 0;
+        if self.layout == Layout::Legacy {
+            flags |= LEGACY_LAYOUT;
+        }
+        if self.key_meshing {
+            flags |= KEY_MESHING;
+        }
+
+        let segment = self.segment.unwrap_or(0);
+
+        output.write_all(&[sbox as u8, flags, padding as u8, segment])?;
+        output.write_all(&self.section_size.to_be_bytes())
+    }
+
+    fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut bytes = [0; Self::SIZE];
+        input.read_exact(&mut bytes)?;
+        let [sbox, flags, padding, segment, section_size @ ..] = bytes;
+
+        let (Some(&sbox), Some(&padding)) = (
+            SBoxSet::ALL.get(sbox as usize),
+            Padding::ALL.get(padding as usize),
+        ) else {
+            return Err(HeaderError::InvalidOptions.into());
+        };
+
+        if flags & !(LEGACY_LAYOUT | KEY_MESHING) != 0 {
+            return Err(HeaderError::InvalidOptions.into());
+        }
+
+        Ok(Self {
+            sbox,
+            layout: if flags & LEGACY_LAYOUT != 0 {
+                Layout::Legacy
+            } else {
+                Layout::Standard
+            },
+            segment: (segment != 0).then_some(segment),
+            padding,
+            key_meshing: flags & KEY_MESHING != 0,
+            section_size: u32::from_be_bytes(section_size),
+        })
+    }
+}
+
+/// What goes before a ciphertext so it can be decrypted without
+/// knowing how it was made:
+/// magic, version, cipher, mode, IV length, plaintext length (big-endian u64), IV,
+/// salt length, zero when the key isn't derived from a passphrase,
+/// then iterations (big-endian u32), key check value and salt, and at last the cipher options:
+/// index of the S-boxes in `SBoxSet::ALL`, flags, padding, segment bytes
+/// and section size (big-endian u32)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub cipher: CipherId,
+    pub mode: ModeId,
+    /// Length of the plaintext in bytes
+    pub len: u64,
+    pub iv: Vec<u8>,
+    pub kdf: Option<KdfParams>,
+    pub options: CipherOptions,
+}
+
+impl Header {
+    /// Header with a fresh random IV of `iv_len` bytes
+    pub fn random(cipher: CipherId, mode: ModeId, len: u64, iv_len: usize) -> Self {
+        let mut iv = vec![0; iv_len];
+        OsRng.fill_bytes(&mut iv);
+
+        Self {
+            cipher,
+            mode,
+            len,
+            iv,
+            kdf: None,
+            options: CipherOptions::default(),
+        }
+    }
+
+    /// Length of the plaintext, unless it was a stream of unknown length
+    pub fn known_len(&self) -> Option<u64> {
+        (self.len != UNKNOWN_LEN).then_some(self.len)
+    }
+
+    /// Size of the header in bytes
+    pub fn size(&self) -> usize {
+        let kdf = self
+            .kdf
+            .as_ref()
+            .map_or(0, |kdf| 4 + KCV_LEN + kdf.salt.len());
+        HEADER_MAGIC.len() + 4 + 8 + self.iv.len() + 1 + kdf + CipherOptions::SIZE
+    }
+
+    pub fn write_to<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let invalid = |what| io::Error::new(io::ErrorKind::InvalidInput, what);
+        let iv_len = u8::try_from(self.iv.len()).map_err(|_| invalid("IV is too long"))?;
+
+        output.write_all(&HEADER_MAGIC)?;
+        output.write_all(&[HEADER_VERSION, self.cipher as u8, self.mode as u8, iv_len])?;
+        output.write_all(&self.len.to_be_bytes())?;
+        output.write_all(&self.iv)?;
+
+        match &self.kdf {
+            None => output.write_all(&[0])?,
+            Some(kdf) => {
+                let salt_len = match u8::try_from(kdf.salt.len()) {
+                    Ok(0) | Err(_) => return Err(invalid("salt must be 1 to 255 bytes")),
+                    Ok(len) => len,
+                };
+
+                if !(1..=MAX_KDF_ITERATIONS).contains(&kdf.iterations) {
+                    return Err(invalid("key derivation iterations out of range"));
+                }
+
+                output.write_all(&[salt_len])?;
+                output.write_all(&kdf.iterations.to_be_bytes())?;
+                output.write_all(&kdf.check_value)?;
+                output.write_all(&kdf.salt)?;
+            }
+        }
+
+        self.options.write_to(output)
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut magic = [0; HEADER_MAGIC.len()];
+        input.read_exact(&mut magic)?;
+
+        if magic != HEADER_MAGIC {
+            return Err(HeaderError::Magic.into());
+        }
+
+        let mut fields = [0; 4];
+        input.read_exact(&mut fields)?;
+        let [version, cipher, mode, iv_len] = fields;
+
+        if version != HEADER_VERSION {
+            return Err(HeaderError::Version(version).into());
+        }
+
+        let mut len = [0; 8];
+        input.read_exact(&mut len)?;
+
+        let mut iv = vec![0; iv_len as usize];
+        input.read_exact(&mut iv)?;
+
+        let mut salt_len = [0];
+        input.read_exact(&mut salt_len)?;
+
+        let kdf = match salt_len[0] {
+            0 => None,
+            salt_len => {
+                let mut iterations = [0; 4];
+                input.read_exact(&mut iterations)?;
+
+                let mut check_value = [0; KCV_LEN];
+                input.read_exact(&mut check_value)?;
+
+                let mut salt = vec![0; salt_len as usize];
+                input.read_exact(&mut salt)?;
+
+                let iterations = u32::from_be_bytes(iterations);
+
+                if !(1..=MAX_KDF_ITERATIONS).contains(&iterations) {
+                    return Err(HeaderError::KdfIterations(iterations).into());
+                }
+
+                Some(KdfParams {
+                    salt,
+                    iterations,
+                    check_value,
+                })
+            }
+        };
+
+        let options = CipherOptions::read_from(input)?;
+
+        Ok(Self {
+            cipher: cipher.try_into()?,
+            mode: mode.try_into()?,
+            len: u64::from_be_bytes(len),
+            iv,
+            kdf,
+            options,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_back() {
+        let mut header = Header::random(CipherId::Kuznyechik, ModeId::Cbc, 12345, 32);
+        let options = CipherOptions {
+            sbox: SBoxSet::CRYPTO_PRO_B,
+            layout: Layout::Legacy,
+            segment: Some(2),
+            padding: Padding::Iso7816,
+            key_meshing: true,
+            section_size: 4096,
+        };
+
+        let kdf = KdfParams {
+            check_value: [1, 2, 3],
+            ..KdfParams::random(1000)
+        };
+
+        for (kdf, options) in [(None, CipherOptions::default()), (Some(kdf), options)] {
+            header.kdf = kdf;
+            header.options = options;
+
+            let mut bytes = Vec::new();
+            header.write_to(&mut bytes).unwrap();
+            bytes.extend_from_slice(b"data");
+
+            assert_eq!(bytes.len(), header.size() + 4);
+
+            let mut reader = bytes.as_slice();
+            assert_eq!(Header::read_from(&mut reader).unwrap(), header);
+            assert_eq!(reader, b"data");
+        }
+    }
+
+    #[test]
+    fn verifies_passphrase() {
+        let kdf = KdfParams {
+            check_value: [1, 2, 3],
+            ..KdfParams::random(1000)
+        };
+
+        assert_eq!(kdf.verify([1, 2, 3]), Ok(()));
+        assert_eq!(kdf.verify([1, 2, 4]), Err(HeaderError::WrongPassphrase));
+    }
+
+    #[test]
+    fn unknown_len() {
+        let mut header = Header::random(CipherId::Gost28147, ModeId::Cfb, 0, 8);
+        assert_eq!(header.known_len(), Some(0));
+
+        header.len = UNKNOWN_LEN;
+        assert_eq!(header.known_len(), None);
+    }
+
+    #[test]
+    fn fresh_iv() {
+        let first = Header::random(CipherId::Belt, ModeId::Cfb, 0, 16);
+        let second = Header::random(CipherId::Belt, ModeId::Cfb, 0, 16);
+        assert_ne!(first.iv, second.iv);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let mut bytes = Vec::new();
+        Header::random(CipherId::Magma, ModeId::Ctr, 1, 8)
+            .write_to(&mut bytes)
+            .unwrap();
+
+        let error = |bytes: &[u8]| {
+            let error = Header::read_from(&mut &bytes[..]).unwrap_err();
+            error.into_inner().map(|e| e.to_string())
+        };
+
+        let mut changed = bytes.clone();
+        changed[0] ^= 1;
+        assert_eq!(error(&changed), Some(HeaderError::Magic.to_string()));
+
+        let mut changed = bytes.clone();
+        changed[4] = HEADER_VERSION + 1;
+        assert_eq!(
+            error(&changed),
+            Some(HeaderError::Version(HEADER_VERSION + 1).to_string())
+        );
+
+        let mut changed = bytes.clone();
+        changed[6] = 99;
+        assert_eq!(
+            error(&changed),
+            Some(HeaderError::UnknownMode(99).to_string())
+        );
+
+        let mut header = Header::random(CipherId::Magma, ModeId::Ctr, 1, 8);
+        for iterations in [0, MAX_KDF_ITERATIONS + 1] {
+            header.kdf = Some(KdfParams::random(MAX_KDF_ITERATIONS));
+
+            let mut changed = Vec::new();
+            header.write_to(&mut changed).unwrap();
+
+            // Iterations go right after the salt length
+            let at = 4 + 4 + 8 + 8 + 1;
+            changed[at..at + 4].copy_from_slice(&iterations.to_be_bytes());
+            assert_eq!(
+                error(&changed),
+                Some(HeaderError::KdfIterations(iterations).to_string())
+            );
+        }
+
+        // The S-boxes are the first option
+        let options = bytes.len() - 8;
+        let mut changed = bytes.clone();
+        changed[options] = 99;
+        assert_eq!(
+            error(&changed),
+            Some(HeaderError::InvalidOptions.to_string())
+        );
+
+        assert!(Header::read_from(&mut &bytes[..10]).is_err());
+    }
+}
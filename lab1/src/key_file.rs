@@ -0,0 +1,386 @@
+//! Key files: either the 32 raw bytes of the key or text like
+//!
+//! ```text
+//! # Comments and empty lines are skipped
+//! algorithm: kuznyechik
+//! key: 8899aabbccddeeff0011223344556677fedcba98765432100123456789abcdef
+//! check: 94bec1
+//! ```
+//!
+//! where the key is in hex or base64, `algorithm` and `check` are optional,
+//! and a text file may also hold the bare key alone. 32 bytes of printable
+//! text are taken for text, so a shorter key in hex or base64 is an error
+//! rather than a raw key.
+
+use std::{fmt, io, path::Path};
+
+use zeroize::Zeroize;
+
+use crate::{BlockCipher, CipherBlock};
+
+pub const KEY_LEN: usize = 32;
+
+/// Length of the key check value in bytes
+pub const KCV_LEN: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyFileError {
+    /// Binary files must hold exactly the key
+    RawLength(usize),
+    /// The key decoded from text isn't `KEY_LEN` bytes
+    Length(usize),
+    /// Neither hex nor base64
+    Encoding(&'static str),
+    /// A line that is neither a field nor the bare key
+    Syntax(usize),
+    UnknownField(String),
+    DuplicateField(String),
+    NoKey,
+    CheckValueLength(usize),
+    /// The key check value doesn't match, the key is corrupted
+    CheckValue,
+    Algorithm {
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for KeyFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RawLength(len) => write!(
+                f,
+                "a raw key file must be {KEY_LEN} bytes, this one is {len}"
+            ),
+            Self::Length(len) => write!(f, "the key must be {KEY_LEN} bytes, it is {len}"),
+            Self::Encoding(what) => write!(f, "the {what} is neither hex nor base64"),
+            Self::Syntax(line) => write!(f, "line {line} of the key file is invalid"),
+            Self::UnknownField(name) => write!(f, "unknown key file field `{name}`"),
+            Self::DuplicateField(name) => write!(f, "key file field `{name}` is repeated"),
+            Self::NoKey => f.write_str("the key file has no key"),
+            Self::CheckValueLength(len) => write!(
+                f,
+                "the key check value must be {KCV_LEN} bytes, it is {len}"
+            ),
+            Self::CheckValue => {
+                f.write_str("the key check value doesn't match, the key is corrupted")
+            }
+            Self::Algorithm { expected, found } => {
+                write!(f, "the key is for {found}, not {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeyFileError {}
+
+impl From<KeyFileError> for io::Error {
+    /// Keeps the message only, so that it is what the command line tools print
+    fn from(value: KeyFileError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, value.to_string())
+    }
+}
+
+/// The first `KCV_LEN` bytes of a zero block encrypted with the key
+pub fn key_check_value<C: BlockCipher>(cipher: &C) -> [u8; KCV_LEN] {
+    let mut bytes = vec![0; C::Block::SIZE];
+    cipher
+        .encrypt(C::Block::default())
+        .write_bytes(&mut bytes, cipher.byte_order());
+
+    bytes[..KCV_LEN].try_into().unwrap()
+}
+
+/// A key read from a file, wiped on drop
+#[derive(Clone)]
+pub struct KeyFile {
+    pub key: [u8; KEY_LEN],
+    /// Name of the cipher the key is for
+    pub algorithm: Option<String>,
+    pub check_value: Option<[u8; KCV_LEN]>,
+}
+
+impl fmt::Debug for KeyFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyFile")
+            .field("algorithm", &self.algorithm)
+            .field("check_value", &self.check_value)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for KeyFile {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Standard base64 with the padding
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    fn value(c: u8) -> Option<u32> {
+        Some(match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        } as u32)
+    }
+
+    let text = text.as_bytes();
+
+    if text.is_empty() || !text.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let padding = text.iter().rev().take_while(|&&c| c == b'=').count();
+
+    if padding > 2 {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+
+    for (i, quad) in text.chunks_exact(4).enumerate() {
+        let last = i == text.len() / 4 - 1;
+        let used = if last { 4 - padding } else { 4 };
+
+        let bits = quad[..used]
+            .iter()
+            .try_fold(0, |acc, &c| Some(acc << 6 | value(c)?))?
+            << (6 * (4 - used));
+
+        let decoded = &bits.to_be_bytes()[1..used];
+
+        // Bits past the end must be zero for the encoding to be the only one
+        if bits.to_be_bytes()[used..].iter().any(|&b| b != 0) {
+            return None;
+        }
+
+        bytes.extend_from_slice(decoded);
+    }
+
+    Some(bytes)
+}
+
+/// Printable ASCII and whitespace, 32 random bytes are such with a chance of about 1e-14
+fn is_text(data: &[u8]) -> bool {
+    data.iter()
+        .all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+}
+
+fn decode(text: &str, what: &'static str) -> Result<Vec<u8>, KeyFileError> {
+    decode_hex(text)
+        .or_else(|| decode_base64(text))
+        .ok_or(KeyFileError::Encoding(what))
+}
+
+impl KeyFile {
+    pub fn parse(data: &[u8]) -> Result<Self, KeyFileError> {
+        if data.len() == KEY_LEN && !is_text(data) {
+            return Ok(Self {
+                key: data.try_into().unwrap(),
+                algorithm: None,
+                check_value: None,
+            });
+        }
+
+        let text = std::str::from_utf8(data).map_err(|_| KeyFileError::RawLength(data.len()))?;
+
+        let mut key = None;
+        let mut algorithm = None;
+        let mut check_value = None;
+
+        let lines = text
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        for (i, line) in lines {
+            let (name, value) = match line.split_once(':') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None if !line.contains(char::is_whitespace) => ("key", line),
+                None => return Err(KeyFileError::Syntax(i + 1)),
+            };
+
+            let field = match name {
+                "key" => &mut key,
+                "algorithm" => &mut algorithm,
+                "check" => &mut check_value,
+                _ => return Err(KeyFileError::UnknownField(name.into())),
+            };
+
+            if field.replace(value).is_some() {
+                return Err(KeyFileError::DuplicateField(name.into()));
+            }
+        }
+
+        let mut decoded = decode(key.ok_or(KeyFileError::NoKey)?, "key")?;
+
+        let Ok(key) = decoded.as_slice().try_into() else {
+            let len = decoded.len();
+            decoded.zeroize();
+            return Err(KeyFileError::Length(len));
+        };
+
+        decoded.zeroize();
+
+        let check_value = match check_value {
+            Some(text) => {
+                let decoded = decode(text, "key check value")?;
+                let len = decoded.len();
+                Some(
+                    decoded
+                        .try_into()
+                        .map_err(|_| KeyFileError::CheckValueLength(len))?,
+                )
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            key,
+            algorithm: algorithm.map(str::to_lowercase),
+            check_value,
+        })
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut data = std::fs::read(path)?;
+        let key_file = Self::parse(&data);
+        data.zeroize();
+
+        Ok(key_file?)
+    }
+
+    /// Checks that the key is for `algorithm` and matches its check value,
+    /// `cipher` is the cipher made from the key
+    pub fn check<C: BlockCipher>(&self, algorithm: &str, cipher: &C) -> Result<(), KeyFileError> {
+        if let Some(found) = &self.algorithm {
+            if found != algorithm {
+                return Err(KeyFileError::Algorithm {
+                    expected: algorithm.into(),
+                    found: found.clone(),
+                });
+            }
+        }
+
+        match self.check_value {
+            Some(check_value) if check_value != key_check_value(cipher) => {
+                Err(KeyFileError::CheckValue)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Kuznyechik;
+
+    const HEX: &str = "8899aabbccddeeff0011223344556677fedcba98765432100123456789abcdef";
+
+    fn key() -> [u8; KEY_LEN] {
+        decode_hex(HEX).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        let raw = KeyFile::parse(&key()).unwrap();
+        assert_eq!(raw.key, key());
+
+        let hex = KeyFile::parse(format!("{HEX}\n").as_bytes()).unwrap();
+        assert_eq!(hex.key, key());
+
+        let base64 = b"iJmqu8zd7v8AESIzRFVmd/7cuph2VDIQASNFZ4mrze8=";
+        assert_eq!(KeyFile::parse(base64).unwrap().key, key());
+    }
+
+    #[test]
+    fn fields() {
+        let cipher = Kuznyechik::new(&key());
+        let kcv = key_check_value(&cipher);
+        let kcv = format!("{:02x}{:02x}{:02x}", kcv[0], kcv[1], kcv[2]);
+
+        let text = format!("# test key\nalgorithm: Kuznyechik\nkey: {HEX}\ncheck: {kcv}\n");
+        let key_file = KeyFile::parse(text.as_bytes()).unwrap();
+
+        assert_eq!(key_file.key, key());
+        assert_eq!(key_file.algorithm.as_deref(), Some("kuznyechik"));
+        assert_eq!(key_file.check("kuznyechik", &cipher), Ok(()));
+        assert!(matches!(
+            key_file.check("magma", &cipher),
+            Err(KeyFileError::Algorithm { .. })
+        ));
+
+        // A flipped key bit
+        let mut corrupted = key_file.clone();
+        corrupted.key[5] ^= 4;
+        let cipher = Kuznyechik::new(&corrupted.key);
+        assert_eq!(
+            corrupted.check("kuznyechik", &cipher),
+            Err(KeyFileError::CheckValue)
+        );
+    }
+
+    #[test]
+    fn rejects() {
+        let parse = |data: &[u8]| KeyFile::parse(data).unwrap_err();
+
+        assert_eq!(parse(&[0xff; 31]), KeyFileError::RawLength(31));
+        assert_eq!(parse(&[0xff; 33]), KeyFileError::RawLength(33));
+        assert_eq!(parse(&HEX.as_bytes()[..62]), KeyFileError::Length(31));
+        assert_eq!(parse(&HEX.as_bytes()[..32]), KeyFileError::Length(16));
+        assert_eq!(
+            parse(b"iJmqu8zd7v8AESIzRFVmd/7cuph2VDIQ"),
+            KeyFileError::Length(24)
+        );
+        assert_eq!(
+            parse(format!("{HEX}00").as_bytes()),
+            KeyFileError::Length(33)
+        );
+        assert_eq!(
+            parse(format!("{}!", &HEX[..63]).as_bytes()),
+            KeyFileError::Encoding("key")
+        );
+        assert_eq!(parse(b"# nothing\n"), KeyFileError::NoKey);
+        assert_eq!(
+            parse(format!("key: {HEX}\nkey: {HEX}").as_bytes()),
+            KeyFileError::DuplicateField("key".into())
+        );
+        assert_eq!(
+            parse(format!("key: {HEX}\ncheck: 0102").as_bytes()),
+            KeyFileError::CheckValueLength(2)
+        );
+        assert_eq!(parse(b"some key\n"), KeyFileError::Syntax(1));
+        assert_eq!(
+            parse(b"kcv: 010203\n"),
+            KeyFileError::UnknownField("kcv".into())
+        );
+    }
+
+    #[test]
+    fn base64_strict() {
+        assert_eq!(decode_base64("YWJj"), Some(b"abc".to_vec()));
+        assert_eq!(decode_base64("YWI="), Some(b"ab".to_vec()));
+        assert_eq!(decode_base64("YQ=="), Some(b"a".to_vec()));
+        assert_eq!(decode_base64("YR=="), None);
+        assert_eq!(decode_base64("YQ="), None);
+        assert_eq!(decode_base64("Y==="), None);
+        assert_eq!(decode_base64("YW=j"), None);
+    }
+}
@@ -0,0 +1,236 @@
+//! Key wrap of RFC 4357 section 6: the content encryption key (CEK) is encrypted
+//! in ECB mode with the key encryption key (KEK) and authenticated by a 4-byte
+//! imitovstavka starting from the user keying material (UKM).
+//!
+//! The KEK is used as is, the CEK can be a key of any 32-byte cipher.
+//! The wrapped key is UKM | CEK_ENC | CEK_MAC.
+
+use std::fmt;
+
+use zeroize::Zeroize;
+
+use super::ecb::*;
+use super::imit::*;
+use super::modes::cipher_feedback;
+use super::simple_swap::*;
+
+pub const UKM_LEN: usize = 8;
+pub const WRAPPED_KEY_LEN: usize = UKM_LEN + 32 + KEY_MAC_LEN;
+
+const KEY_MAC_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyUnwrapError;
+
+impl fmt::Display for KeyUnwrapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the imitovstavka of the wrapped key doesn't match")
+    }
+}
+
+impl std::error::Error for KeyUnwrapError {}
+
+impl From<KeyUnwrapError> for std::io::Error {
+    fn from(value: KeyUnwrapError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, value)
+    }
+}
+
+/// CryptoPro KEK diversification of section 6.3: eight CFB encryptions of the key
+/// with itself, the IV of step `i` made of the key words chosen by the bits of `ukm[i]`
+pub fn cryptopro_diversify(kek: &MagmaKey, ukm: &[u8; UKM_LEN], params: &Params) -> MagmaKey {
+    let mut key = kek.clone();
+
+    for &bits in ukm {
+        let (set, unset) = key.words().iter().enumerate().fold(
+            (0u32, 0u32),
+            |(set, unset), (j, &word)| match bits >> j & 1 {
+                1 => (set.wrapping_add(word), unset),
+                _ => (set, unset.wrapping_add(word)),
+            },
+        );
+
+        let mut blocks: [u64; 4] = std::array::from_fn(|i| {
+            let [low, high] = [key.words()[i * 2], key.words()[i * 2 + 1]];
+            (high as u64) << 32 | low as u64
+        });
+
+        let iv = (unset as u64) << 32 | set as u64;
+        cipher_feedback(&Gost28147::with_params(key, *params), iv, &mut blocks);
+
+        key = MagmaKey::new(std::array::from_fn(|j| {
+            (blocks[j / 2] >> (j % 2 * 32)) as u32
+        }));
+    }
+
+    key
+}
+
+/// GOST 28147-89 key wrap of section 6.1
+pub fn gost28147_wrap(
+    kek: &MagmaKey,
+    ukm: &[u8; UKM_LEN],
+    cek: &[u8; 32],
+    params: &Params,
+) -> [u8; WRAPPED_KEY_LEN] {
+    let order = params.order;
+
+    let mut mac = Imitovstavka::with_iv(kek.clone(), *params, order.block(*ukm));
+    let mut blocks: [u64; 4] =
+        std::array::from_fn(|i| order.block(cek[i * 8..i * 8 + 8].try_into().unwrap()));
+
+    mac.process_blocks(&blocks);
+    simple_replacement_mode_block(&mut blocks, kek, params);
+
+    let mut wrapped = [0; WRAPPED_KEY_LEN];
+    wrapped[..UKM_LEN].copy_from_slice(ukm);
+    wrapped[UKM_LEN..UKM_LEN + 32]
+        .chunks_exact_mut(8)
+        .zip(blocks)
+        .for_each(|(bytes, block)| bytes.copy_from_slice(&order.bytes(block)));
+    wrapped[UKM_LEN + 32..].copy_from_slice(&mac.finish(&[], KEY_MAC_LEN));
+
+    wrapped
+}
+
+/// GOST 28147-89 key unwrap of section 6.2
+pub fn gost28147_unwrap(
+    kek: &MagmaKey,
+    wrapped: &[u8; WRAPPED_KEY_LEN],
+    params: &Params,
+) -> Result<[u8; 32], KeyUnwrapError> {
+    let order = params.order;
+    let (ukm, rest) = wrapped.split_at(UKM_LEN);
+    let (cek_enc, cek_mac) = rest.split_at(32);
+
+    let mut blocks: [u64; 4] =
+        std::array::from_fn(|i| order.block(cek_enc[i * 8..i * 8 + 8].try_into().unwrap()));
+    simple_replacement_mode_block_decrypt(&mut blocks, kek, params);
+
+    let mut mac = Imitovstavka::with_iv(kek.clone(), *params, order.block(ukm.try_into().unwrap()));
+    mac.process_blocks(&blocks);
+
+    let mut cek = [0; 32];
+    cek.chunks_exact_mut(8)
+        .zip(blocks)
+        .for_each(|(bytes, block)| bytes.copy_from_slice(&order.bytes(block)));
+
+    if !mac_eq(&mac.finish(&[], KEY_MAC_LEN), cek_mac) {
+        cek.zeroize();
+        return Err(KeyUnwrapError);
+    }
+
+    Ok(cek)
+}
+
+/// CryptoPro key wrap of section 6.4: the GOST 28147-89 one under the diversified KEK
+pub fn cryptopro_wrap(
+    kek: &MagmaKey,
+    ukm: &[u8; UKM_LEN],
+    cek: &[u8; 32],
+    params: &Params,
+) -> [u8; WRAPPED_KEY_LEN] {
+    gost28147_wrap(&cryptopro_diversify(kek, ukm, params), ukm, cek, params)
+}
+
+/// CryptoPro key unwrap of section 6.5
+pub fn cryptopro_unwrap(
+    kek: &MagmaKey,
+    wrapped: &[u8; WRAPPED_KEY_LEN],
+    params: &Params,
+) -> Result<[u8; 32], KeyUnwrapError> {
+    let ukm = wrapped[..UKM_LEN].try_into().unwrap();
+    gost28147_unwrap(&cryptopro_diversify(kek, ukm, params), wrapped, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_bytes(key: &MagmaKey) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        bytes
+            .chunks_exact_mut(4)
+            .zip(key.words())
+            .for_each(|(bytes, word)| bytes.copy_from_slice(&word.to_le_bytes()));
+        bytes
+    }
+
+    fn key_from_bytes(bytes: &[u8; 32]) -> MagmaKey {
+        MagmaKey::new(std::array::from_fn(|i| {
+            u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap())
+        }))
+    }
+
+    const PARAMS: Params = Params::new(SBoxSet::CRYPTO_PRO_A);
+    const UKM: [u8; UKM_LEN] = [0x01, 0x80, 0xff, 0x00, 0x5a, 0xa5, 0x33, 0xcc];
+
+    fn kek() -> MagmaKey {
+        key_from_bytes(&std::array::from_fn(|i| i as u8 * 7 + 3))
+    }
+
+    fn cek() -> [u8; 32] {
+        std::array::from_fn(|i| 0xff - i as u8)
+    }
+
+    fn from_hex<const N: usize>(hex: &str) -> [u8; N] {
+        std::array::from_fn(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap())
+    }
+
+    #[test]
+    fn known_answer() {
+        // Computed with the GOST 28147-89 ECB, CFB and imitovstavka of libgcrypt 1.10
+        // with the CryptoPro A S-boxes, following sections 6.1 and 6.3
+        assert_eq!(
+            gost28147_wrap(&kek(), &UKM, &cek(), &PARAMS),
+            from_hex(concat!(
+                "0180ff005aa533cc",
+                "9e4657fe9fb2588288c6392550b61c6ba5560a313f732e5fcf394a45bcd901fb",
+                "7c4f408b"
+            ))
+        );
+        assert_eq!(
+            key_bytes(&cryptopro_diversify(&kek(), &UKM, &PARAMS)),
+            from_hex::<32>("677adfffe84ec22d07729823b6b776f60cd3a321094e91597f661ab246f61ea8")
+        );
+        assert_eq!(
+            cryptopro_wrap(&kek(), &UKM, &cek(), &PARAMS),
+            from_hex(concat!(
+                "0180ff005aa533cc",
+                "801d43f2e7c06f9abab26e1c0f559ca0a3db5d1a6696f55b76e9c5556d86e680",
+                "2f26c8d9"
+            ))
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let wrapped = gost28147_wrap(&kek(), &UKM, &cek(), &PARAMS);
+        let unwrapped = gost28147_unwrap(&kek(), &wrapped, &PARAMS).unwrap();
+        assert_eq!(unwrapped, cek());
+
+        let wrapped = cryptopro_wrap(&kek(), &UKM, &cek(), &PARAMS);
+        let unwrapped = cryptopro_unwrap(&kek(), &wrapped, &PARAMS).unwrap();
+        assert_eq!(unwrapped, cek());
+
+        // The diversified KEK is a different one
+        assert_ne!(wrapped, gost28147_wrap(&kek(), &UKM, &cek(), &PARAMS));
+    }
+
+    #[test]
+    fn detects_changes() {
+        let wrapped = cryptopro_wrap(&kek(), &UKM, &cek(), &PARAMS);
+
+        for i in 0..WRAPPED_KEY_LEN {
+            let mut changed = wrapped;
+            changed[i] ^= 0x10;
+            assert_eq!(
+                cryptopro_unwrap(&kek(), &changed, &PARAMS).unwrap_err(),
+                KeyUnwrapError
+            );
+        }
+
+        let other_kek = key_from_bytes(&[1; 32]);
+        assert!(gost28147_unwrap(&other_kek, &wrapped, &PARAMS).is_err());
+    }
+}
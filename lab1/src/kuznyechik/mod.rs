@@ -0,0 +1,221 @@
+mod modes;
+mod tables;
+
+pub use modes::*;
+use tables::*;
+
+/// A block as bytes a15..a0, the first byte is the most significant one
+type Block = [u8; 16];
+
+fn xor(block: &mut Block, other: &Block) {
+    block.iter_mut().zip(other).for_each(|(a, b)| *a ^= b);
+}
+
+fn substitute(block: &mut Block) {
+    block.iter_mut().for_each(|a| *a = PI[*a as usize]);
+}
+
+fn substitute_inv(block: &mut Block) {
+    block.iter_mut().for_each(|a| *a = PI_INV[*a as usize]);
+}
+
+fn linear(block: &Block) -> u8 {
+    block
+        .iter()
+        .zip(&L_PRODUCTS)
+        .fold(0, |acc, (&a, products)| acc ^ products[a as usize])
+}
+
+fn r(block: &mut Block) {
+    let a15 = linear(block);
+    block.copy_within(..15, 1);
+    block[0] = a15;
+}
+
+fn r_inv(block: &mut Block) {
+    block.rotate_left(1);
+    block[15] = linear(block);
+}
+
+fn l(block: &mut Block) {
+    (0..16).for_each(|_| r(block));
+}
+
+fn l_inv(block: &mut Block) {
+    (0..16).for_each(|_| r_inv(block));
+}
+
+/// GOST R 34.12-2015 "Kuznyechik", the 128-bit block cipher of RFC 7801
+#[derive(Debug, Clone)]
+pub struct Kuznyechik {
+    round_keys: [Block; 10],
+}
+
+impl Kuznyechik {
+    /// The substitution π of GOST R 34.12-2015
+    pub const S_BOX: [u8; 256] = PI;
+
+    pub fn new(key: &[u8; 32]) -> Self {
+        let mut round_keys = [[0; 16]; 10];
+        round_keys[0].copy_from_slice(&key[..16]);
+        round_keys[1].copy_from_slice(&key[16..]);
+
+        let (mut a, mut b) = (round_keys[0], round_keys[1]);
+
+        for i in 0..32 {
+            let mut constant = (i as u128 + 1).to_be_bytes();
+            l(&mut constant);
+
+            // A round of the Feistel network with the constant as the key
+            let mut next = a;
+            xor(&mut next, &constant);
+            substitute(&mut next);
+            l(&mut next);
+            xor(&mut next, &b);
+
+            (a, b) = (next, a);
+
+            if i % 8 == 7 {
+                round_keys[i / 8 * 2 + 2] = a;
+                round_keys[i / 8 * 2 + 3] = b;
+            }
+        }
+
+        Self { round_keys }
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8; 16]) {
+        for key in &self.round_keys[..9] {
+            xor(block, key);
+            substitute(block);
+            l(block);
+        }
+        xor(block, &self.round_keys[9]);
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8; 16]) {
+        xor(block, &self.round_keys[9]);
+        for key in self.round_keys[..9].iter().rev() {
+            l_inv(block);
+            substitute_inv(block);
+            xor(block, key);
+        }
+    }
+
+    /// ACPKM of RFC 8645: the cipher keyed with D1 || D2 encrypted with the current key
+    pub fn acpkm(&self) -> Self {
+        let mut key: [u8; 32] = std::array::from_fn(|i| 0x80 + i as u8);
+        key.chunks_exact_mut(16)
+            .for_each(|d| self.encrypt_block(d.try_into().unwrap()));
+        Self::new(&key)
+    }
+
+    /// Encrypts a block taken as a big-endian number
+    pub fn encrypt(&self, block: u128) -> u128 {
+        let mut bytes = block.to_be_bytes();
+        self.encrypt_block(&mut bytes);
+        u128::from_be_bytes(bytes)
+    }
+
+    pub fn decrypt(&self, block: u128) -> u128 {
+        let mut bytes = block.to_be_bytes();
+        self.decrypt_block(&mut bytes);
+        u128::from_be_bytes(bytes)
+    }
+}
+
+impl crate::BlockCipher for Kuznyechik {
+    type Block = u128;
+    type Key = [u8; 32];
+
+    fn new(key: &[u8; 32]) -> Self {
+        Self::new(key)
+    }
+
+    fn encrypt(&self, block: u128) -> u128 {
+        Self::encrypt(self, block)
+    }
+
+    fn decrypt(&self, block: u128) -> u128 {
+        Self::decrypt(self, block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex<const N: usize>(hex: &str) -> [u8; N] {
+        std::array::from_fn(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap())
+    }
+
+    fn rfc_7801_key() -> Kuznyechik {
+        Kuznyechik::new(&from_hex(
+            "8899aabbccddeeff0011223344556677fedcba98765432100123456789abcdef",
+        ))
+    }
+
+    #[test]
+    fn transformations() {
+        // RFC 7801 sections 5.1 - 5.3
+        let mut block = from_hex("ffeeddccbbaa99881122334455667700");
+        substitute(&mut block);
+        assert_eq!(block, from_hex::<16>("b66cd8887d38e8d77765aeea0c9a7efc"));
+        substitute_inv(&mut block);
+        assert_eq!(block, from_hex::<16>("ffeeddccbbaa99881122334455667700"));
+
+        let mut block = from_hex("00000000000000000000000000000100");
+        r(&mut block);
+        assert_eq!(block, from_hex::<16>("94000000000000000000000000000001"));
+        r_inv(&mut block);
+        assert_eq!(block, from_hex::<16>("00000000000000000000000000000100"));
+
+        let mut block = from_hex("64a59400000000000000000000000000");
+        l(&mut block);
+        assert_eq!(block, from_hex::<16>("d456584dd0e3e84cc3166e4b7fa2890d"));
+        l_inv(&mut block);
+        assert_eq!(block, from_hex::<16>("64a59400000000000000000000000000"));
+    }
+
+    #[test]
+    fn key_schedule() {
+        // RFC 7801 section 5.4
+        let cipher = rfc_7801_key();
+        assert_eq!(
+            cipher.round_keys[2],
+            from_hex::<16>("db31485315694343228d6aef8cc78c44")
+        );
+        assert_eq!(
+            cipher.round_keys[9],
+            from_hex::<16>("72e9dd7416bcf45b755dbaa88e4a4043")
+        );
+    }
+
+    #[test]
+    fn acpkm_key() {
+        // RFC 8645 appendix A.1, the second section key
+        let next = rfc_7801_key().acpkm();
+        assert_eq!(
+            next.round_keys[..2].concat(),
+            from_hex::<32>("2666ed40ae687811745ca0b448f57a7b390adb5780307e8e9659ac403ae60c60")
+        );
+    }
+
+    #[test]
+    fn rfc_7801_vector() {
+        // RFC 7801 sections 5.5 and 5.6
+        let cipher = rfc_7801_key();
+        let mut block = from_hex("1122334455667700ffeeddccbbaa9988");
+
+        cipher.encrypt_block(&mut block);
+        assert_eq!(block, from_hex::<16>("7f679d90bebc24305a468d42b9d4edcd"));
+
+        cipher.decrypt_block(&mut block);
+        assert_eq!(block, from_hex::<16>("1122334455667700ffeeddccbbaa9988"));
+
+        assert_eq!(
+            cipher.encrypt(0x1122334455667700ffeeddccbbaa9988),
+            0x7f679d90bebc24305a468d42b9d4edcd
+        );
+    }
+}
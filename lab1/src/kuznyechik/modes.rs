@@ -0,0 +1,296 @@
+//! GOST R 34.13-2015 modes for Kuznyechik, blocks are big-endian numbers
+
+use super::Kuznyechik;
+use crate::modes::*;
+
+pub fn kuznyechik_simple_replacement(cipher: &Kuznyechik, input: &mut [u128]) {
+    simple_replacement(cipher, input)
+}
+
+pub fn kuznyechik_simple_replacement_decrypt(cipher: &Kuznyechik, input: &mut [u128]) {
+    simple_replacement_decrypt(cipher, input)
+}
+
+/// The first counter value: the 64-bit IV followed by zeroes
+pub fn kuznyechik_counter_init(iv: u64) -> u128 {
+    (iv as u128) << 64
+}
+
+/// Counter mode: xors every block with the encrypted counter and increments it.
+/// Returns the counter for the next block.
+pub fn kuznyechik_counter(cipher: &Kuznyechik, counter: u128, input: &mut [u128]) -> u128 {
+    crate::modes::counter(cipher, counter, input)
+}
+
+/// Counter mode where the key goes through ACPKM after every section
+#[derive(Debug, Clone)]
+pub struct KuznyechikAcpkmCtr {
+    cipher: Kuznyechik,
+    counter: u128,
+    section: usize,
+    used: usize,
+}
+
+impl KuznyechikAcpkmCtr {
+    /// Sections are given in blocks
+    pub fn new(cipher: Kuznyechik, iv: u64, section: usize) -> Self {
+        assert!(section > 0, "section must hold a block");
+
+        Self {
+            cipher,
+            counter: kuznyechik_counter_init(iv),
+            section,
+            used: 0,
+        }
+    }
+}
+
+/// Encryption and decryption are the same operation
+pub fn kuznyechik_acpkm_counter(state: &mut KuznyechikAcpkmCtr, input: &mut [u128]) {
+    input.iter_mut().for_each(|block| {
+        if state.used == state.section {
+            state.cipher = state.cipher.acpkm();
+            state.used = 0;
+        }
+
+        *block ^= state.cipher.encrypt(state.counter);
+        state.counter = state.counter.wrapping_add(1);
+        state.used += 1;
+    });
+}
+
+/// Cipher block chaining with a register of `register.len()` blocks
+pub fn kuznyechik_cipher_block_chaining(
+    cipher: &Kuznyechik,
+    register: &mut [u128],
+    input: &mut [u128],
+) {
+    cipher_block_chaining(cipher, register, input)
+}
+
+pub fn kuznyechik_cipher_block_chaining_decrypt(
+    cipher: &Kuznyechik,
+    register: &mut [u128],
+    input: &mut [u128],
+) {
+    cipher_block_chaining_decrypt(cipher, register, input)
+}
+
+/// Output feedback with a register of `register.len()` blocks.
+/// Encryption and decryption are the same operation.
+pub fn kuznyechik_output_feedback(cipher: &Kuznyechik, register: &mut [u128], input: &mut [u128]) {
+    output_feedback(cipher, register, input)
+}
+
+fn kuznyechik_cipher_feedback_core(
+    cipher: &Kuznyechik,
+    register: &mut [u8],
+    segment: usize,
+    input: &mut [u8],
+    decrypt: bool,
+) {
+    assert!(register.len() >= 16, "register must hold at least a block");
+    assert!((1..=16).contains(&segment), "segment must fit in a block");
+
+    input.chunks_mut(segment).for_each(|chunk| {
+        let mut gamma: [u8; 16] = register[..16].try_into().unwrap();
+        cipher.encrypt_block(&mut gamma);
+
+        register.rotate_left(chunk.len());
+        let fed_back = register.len() - chunk.len();
+
+        chunk
+            .iter_mut()
+            .zip(gamma)
+            .zip(&mut register[fed_back..])
+            .for_each(|((byte, gamma), fed_back)| {
+                let encrypted = if decrypt { *byte } else { *byte ^ gamma };
+                *byte ^= gamma;
+                *fed_back = encrypted;
+            });
+    });
+}
+
+/// Cipher feedback with `segment` bytes of feedback and a register of `register.len()` bytes.
+/// To continue in the next call, `input` must be a multiple of `segment`.
+pub fn kuznyechik_cipher_feedback(
+    cipher: &Kuznyechik,
+    register: &mut [u8],
+    segment: usize,
+    input: &mut [u8],
+) {
+    kuznyechik_cipher_feedback_core(cipher, register, segment, input, false)
+}
+
+pub fn kuznyechik_cipher_feedback_decrypt(
+    cipher: &Kuznyechik,
+    register: &mut [u8],
+    segment: usize,
+    input: &mut [u8],
+) {
+    kuznyechik_cipher_feedback_core(cipher, register, segment, input, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // GOST R 34.13-2015 appendix A.1
+    const PLAIN: [u128; 4] = [
+        0x1122334455667700ffeeddccbbaa9988,
+        0x00112233445566778899aabbcceeff0a,
+        0x112233445566778899aabbcceeff0a00,
+        0x2233445566778899aabbcceeff0a0011,
+    ];
+    const IV: [u128; 2] = [
+        0x1234567890abcef0a1b2c3d4e5f00112,
+        0x23344556677889901213141516171819,
+    ];
+
+    fn test_cipher() -> Kuznyechik {
+        let mut bytes = [0; 32];
+        bytes[..16].copy_from_slice(&0x8899aabbccddeeff0011223344556677_u128.to_be_bytes());
+        bytes[16..].copy_from_slice(&0xfedcba98765432100123456789abcdef_u128.to_be_bytes());
+        Kuznyechik::new(&bytes)
+    }
+
+    #[test]
+    fn ecb_vector() {
+        let cipher = test_cipher();
+        let mut blocks = PLAIN;
+
+        kuznyechik_simple_replacement(&cipher, &mut blocks);
+        assert_eq!(
+            blocks,
+            [
+                0x7f679d90bebc24305a468d42b9d4edcd,
+                0xb429912c6e0032f9285452d76718d08b,
+                0xf0ca33549d247ceef3f5a5313bd4b157,
+                0xd0b09ccde830b9eb3a02c4c5aa8ada98,
+            ]
+        );
+
+        kuznyechik_simple_replacement_decrypt(&cipher, &mut blocks);
+        assert_eq!(blocks, PLAIN);
+    }
+
+    #[test]
+    fn ctr_vector() {
+        let cipher = test_cipher();
+        let counter = kuznyechik_counter_init(0x1234567890abcef0);
+        let mut blocks = PLAIN;
+
+        let (head, tail) = blocks.split_at_mut(1);
+        let next = kuznyechik_counter(&cipher, counter, head);
+        kuznyechik_counter(&cipher, next, tail);
+
+        assert_eq!(
+            blocks,
+            [
+                0xf195d8bec10ed1dbd57b5fa240bda1b8,
+                0x85eee733f6a13e5df33ce4b33c45dee4,
+                0xa5eae88be6356ed3d5e877f13564a3a5,
+                0xcb91fab1f20cbab6d1c6d15820bdba73,
+            ]
+        );
+    }
+
+    #[test]
+    fn cbc_vector() {
+        let cipher = test_cipher();
+        let mut register = IV;
+        let mut blocks = PLAIN;
+
+        kuznyechik_cipher_block_chaining(&cipher, &mut register, &mut blocks);
+        assert_eq!(
+            blocks,
+            [
+                0x689972d4a085fa4d90e52e3d6d7dcc27,
+                0x2826e661b478eca6af1e8e448d5ea5ac,
+                0xfe7babf1e91999e85640e8b0f49d90d0,
+                0x167688065a895c631a2d9a1560b63970,
+            ]
+        );
+
+        let mut register = IV;
+        kuznyechik_cipher_block_chaining_decrypt(&cipher, &mut register, &mut blocks);
+        assert_eq!(blocks, PLAIN);
+    }
+
+    #[test]
+    fn ofb_vector() {
+        let cipher = test_cipher();
+        let mut register = IV;
+        let mut blocks = PLAIN;
+
+        kuznyechik_output_feedback(&cipher, &mut register, &mut blocks);
+        assert_eq!(
+            blocks,
+            [
+                0x81800a59b1842b24ff1f795e897abd95,
+                0xed5b47a7048cfab48fb521369d9326bf,
+                0x66a257ac3ca0b8b1c80fe7fc10288a13,
+                0x203ebbc066138660a0292243f6903150,
+            ]
+        );
+    }
+
+    #[test]
+    fn cfb_vector() {
+        let cipher = test_cipher();
+        let mut register: Vec<u8> = IV.iter().flat_map(|b| b.to_be_bytes()).collect();
+        let mut bytes: Vec<u8> = PLAIN.iter().flat_map(|b| b.to_be_bytes()).collect();
+
+        kuznyechik_cipher_feedback(&cipher, &mut register, 16, &mut bytes);
+
+        let expected: Vec<u8> = [
+            0x81800a59b1842b24ff1f795e897abd95_u128,
+            0xed5b47a7048cfab48fb521369d9326bf,
+            0x79f2a8eb5cc68d38842d264e97a238b5,
+            0x4ffebecd4e922de6c75bd9dd44fbf4d1,
+        ]
+        .iter()
+        .flat_map(|b| b.to_be_bytes())
+        .collect();
+        assert_eq!(bytes, expected);
+
+        let mut register: Vec<u8> = IV.iter().flat_map(|b| b.to_be_bytes()).collect();
+        kuznyechik_cipher_feedback_decrypt(&cipher, &mut register, 16, &mut bytes);
+        assert!(bytes
+            .chunks(16)
+            .map(|b| u128::from_be_bytes(b.try_into().unwrap()))
+            .eq(PLAIN));
+    }
+
+    #[test]
+    fn acpkm_ctr_vector() {
+        // Sections of two blocks, as in RFC 8645 appendix A.1
+        let mut state = KuznyechikAcpkmCtr::new(test_cipher(), 0x1234567890abcef0, 2);
+        let mut blocks = [
+            0x1122334455667700ffeeddccbbaa9988,
+            0x00112233445566778899aabbcceeff0a,
+            0x112233445566778899aabbcceeff0a00,
+            0x2233445566778899aabbcceeff0a0011,
+            0x33445566778899aabbcceeff0a001122,
+            0x445566778899aabbcceeff0a00112233,
+            0x5566778899aabbcceeff0a0011223344,
+        ];
+
+        let (head, tail) = blocks.split_at_mut(3);
+        kuznyechik_acpkm_counter(&mut state, head);
+        kuznyechik_acpkm_counter(&mut state, tail);
+
+        assert_eq!(
+            blocks,
+            [
+                0xf195d8bec10ed1dbd57b5fa240bda1b8,
+                0x85eee733f6a13e5df33ce4b33c45dee4,
+                0x4bceeb8f646f4c55001706275e85e800,
+                0x587c4df568d094393e4834afd0805046,
+                0xcf30f57686aeece11cfc6c316b8a896e,
+                0xdffd07ec813636460c4f3b743423163e,
+                0x6409a9c282fac8d469d221e7fbd6de5d,
+            ]
+        );
+    }
+}
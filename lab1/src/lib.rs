@@ -1,3 +1,47 @@
+mod acpkm;
+mod block_cipher;
+mod cbc;
+mod cfb_stream;
+mod cfm;
+mod ctr;
+mod ctr_stream;
+mod differential;
+mod ecb;
+mod header;
+mod imit;
+mod key_file;
+mod key_wrap;
+mod kuznyechik;
+mod magma;
+mod mgm;
+mod modes;
+mod ofb;
+mod padding;
+mod parallel;
+mod sbox_analysis;
 mod simple_swap;
+mod stdio;
 
+pub use acpkm::*;
+pub use block_cipher::*;
+pub use cbc::*;
+pub use cfb_stream::*;
+pub use cfm::*;
+pub use ctr::*;
+pub use ctr_stream::*;
+pub use differential::*;
+pub use ecb::*;
+pub use header::*;
+pub use imit::*;
+pub use key_file::*;
+pub use key_wrap::*;
+pub use kuznyechik::*;
+pub use magma::*;
+pub use mgm::*;
+pub use modes::*;
+pub use ofb::*;
+pub use padding::*;
+pub use parallel::*;
+pub use sbox_analysis::*;
 pub use simple_swap::*;
+pub use stdio::*;
@@ -0,0 +1,174 @@
+use super::simple_swap::*;
+
+/// GOST R 34.12-2015 "Magma": the 28147-89 cipher with the fixed TC26 S-boxes
+/// and keys and blocks in big-endian byte order, as in RFC 8891
+#[derive(Debug, Clone)]
+pub struct Magma {
+    key: MagmaKey,
+}
+
+impl Magma {
+    pub const PARAMS: Params = Params {
+        sbox: SBoxSet::TC26_Z,
+        layout: Layout::Standard,
+        order: ByteOrder::Big,
+        backend: Backend::Auto,
+    };
+
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            key: MagmaKey::new(std::array::from_fn(|i| {
+                u32::from_be_bytes(key[i * 4..i * 4 + 4].try_into().unwrap())
+            })),
+        }
+    }
+
+    /// The expanded key, to be used with the modes and `Magma::PARAMS`
+    pub fn key(&self) -> &MagmaKey {
+        &self.key
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8; 8]) {
+        let input = u64::from_be_bytes(*block);
+        *block = simple_swap_with(input, &self.key, &Self::PARAMS).to_be_bytes();
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8; 8]) {
+        let input = u64::from_be_bytes(*block);
+        *block = simple_swap_decrypt_with(input, &self.key, &Self::PARAMS).to_be_bytes();
+    }
+}
+
+/// The first counter of the counter mode of GOST R 34.13-2015:
+/// the IV of half a block followed by zeroes
+pub fn magma_counter_init(iv: u32) -> u64 {
+    (iv as u64) << 32
+}
+
+/// Blocks are big-endian numbers
+impl crate::BlockCipher for Magma {
+    type Block = u64;
+    type Key = [u8; 32];
+
+    fn new(key: &[u8; 32]) -> Self {
+        Self::new(key)
+    }
+
+    fn encrypt(&self, block: u64) -> u64 {
+        simple_swap_with(block, &self.key, &Self::PARAMS)
+    }
+
+    fn decrypt(&self, block: u64) -> u64 {
+        simple_swap_decrypt_with(block, &self.key, &Self::PARAMS)
+    }
+
+    fn encrypt_blocks(&self, blocks: &mut [u64]) {
+        simple_swap_blocks_with(blocks, &self.key, &Self::PARAMS)
+    }
+
+    fn decrypt_blocks(&self, blocks: &mut [u64]) {
+        simple_swap_decrypt_blocks_with(blocks, &self.key, &Self::PARAMS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfm::cipher_feedback_mode_segments;
+
+    fn from_hex<const N: usize>(hex: &str) -> [u8; N] {
+        std::array::from_fn(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap())
+    }
+
+    fn rfc_8891_key() -> Magma {
+        Magma::new(&from_hex(
+            "ffeeddccbbaa99887766554433221100f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff",
+        ))
+    }
+
+    #[test]
+    fn key_schedule() {
+        // RFC 8891 appendix A.3, the first eight round keys
+        assert_eq!(
+            rfc_8891_key().key().words(),
+            [
+                0xffeeddcc, 0xbbaa9988, 0x77665544, 0x33221100, 0xf0f1f2f3, 0xf4f5f6f7, 0xf8f9fafb,
+                0xfcfdfeff,
+            ]
+        );
+    }
+
+    #[test]
+    fn rfc_8891_vector() {
+        let magma = rfc_8891_key();
+        let mut block = from_hex("fedcba9876543210");
+
+        magma.encrypt_block(&mut block);
+        assert_eq!(block, from_hex::<8>("4ee901e5c2d8ca3d"));
+
+        magma.decrypt_block(&mut block);
+        assert_eq!(block, from_hex::<8>("fedcba9876543210"));
+    }
+
+    #[test]
+    fn gost_34_13_ecb_vector() {
+        // GOST R 34.13-2015 appendix A.2.1
+        let magma = rfc_8891_key();
+        let plain = [
+            "92def06b3c130a59",
+            "db54c704f8189d20",
+            "4a98fb2e67a8024c",
+            "8912409b17b57e41",
+        ];
+        let cipher = [
+            "2b073f0494f372a0",
+            "de70e715d3556e48",
+            "11d8d9e9eacfbc1e",
+            "7c68260996c67efb",
+        ];
+
+        for (plain, cipher) in plain.into_iter().zip(cipher) {
+            let mut block = from_hex(plain);
+            magma.encrypt_block(&mut block);
+            assert_eq!(block, from_hex::<8>(cipher));
+        }
+    }
+
+    #[test]
+    fn gost_34_13_ctr_vector() {
+        // GOST R 34.13-2015 appendix A.2.2
+        let magma = rfc_8891_key();
+        let mut blocks = [
+            "92def06b3c130a59",
+            "db54c704f8189d20",
+            "4a98fb2e67a8024c",
+            "8912409b17b57e41",
+        ]
+        .map(|hex| u64::from_be_bytes(from_hex(hex)));
+
+        let next = crate::counter(&magma, magma_counter_init(0x12345678), &mut blocks);
+
+        assert_eq!(
+            blocks.map(u64::to_be_bytes),
+            [
+                "4e98110c97b7b93c",
+                "3e250d93d6e85d69",
+                "136d868807b2dbef",
+                "568eb680ab52a12d",
+            ]
+            .map(from_hex)
+        );
+        assert_eq!(next, 0x1234567800000004);
+    }
+
+    #[test]
+    fn byte_order_of_modes() {
+        // GOST R 34.13-2015 appendix A.2.5 with s = 64: the modes see the same bytes
+        let magma = rfc_8891_key();
+        let mut register = from_hex::<16>("1234567890abcdef234567890abcdef1");
+        let mut data = from_hex::<16>("92def06b3c130a59db54c704f8189d20");
+
+        cipher_feedback_mode_segments(&mut register, 8, &mut data, magma.key(), &Magma::PARAMS);
+        assert_eq!(data, from_hex::<16>("db37e0e266903c830d46644c1f9a089c"));
+    }
+}
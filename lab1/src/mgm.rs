@@ -0,0 +1,343 @@
+use std::fmt;
+use std::ops::BitXor;
+
+/// A block of a GOST R 34.12-2015 cipher usable in MGM, taken as a big-endian number
+pub trait MgmBlock: Copy + Default + BitXor<Output = Self> {
+    const SIZE: usize;
+
+    /// Reads up to a block of bytes, padding them with zeroes on the right
+    fn from_bytes(bytes: &[u8]) -> Self;
+    /// Writes the first `bytes.len()` bytes of the block
+    fn write_bytes(self, bytes: &mut [u8]);
+    /// Multiplication in GF(2^n)
+    fn gf_mul(self, other: Self) -> Self;
+    /// Increments the left half of the block modulo 2^(n/2)
+    fn incr_left(self) -> Self;
+    /// Increments the right half of the block modulo 2^(n/2)
+    fn incr_right(self) -> Self;
+    /// Sets the most significant bit
+    fn with_msb(self, set: bool) -> Self;
+    /// Bit lengths of the associated data and of the ciphertext, n/2 bits each
+    fn lengths(associated: u64, data: u64) -> Self;
+}
+
+macro_rules! mgm_block {
+    ($block:ty, $half:ty, $poly:expr) => {
+        impl MgmBlock for $block {
+            const SIZE: usize = std::mem::size_of::<$block>();
+
+            fn from_bytes(bytes: &[u8]) -> Self {
+                let mut block = [0; Self::SIZE];
+                block[..bytes.len()].copy_from_slice(bytes);
+                Self::from_be_bytes(block)
+            }
+
+            fn write_bytes(self, bytes: &mut [u8]) {
+                bytes.copy_from_slice(&self.to_be_bytes()[..bytes.len()]);
+            }
+
+            fn gf_mul(self, other: Self) -> Self {
+                let (mut a, mut b, mut product) = (self, other, 0);
+                for _ in 0..<$block>::BITS {
+                    product ^= a & (b & 1).wrapping_neg();
+                    a = (a << 1) ^ ($poly & (a >> (<$block>::BITS - 1)).wrapping_neg());
+                    b >>= 1;
+                }
+                product
+            }
+
+            fn incr_left(self) -> Self {
+                let half = <$half>::BITS;
+                let left = ((self >> half) as $half).wrapping_add(1);
+                ((left as $block) << half) | (self as $half as $block)
+            }
+
+            fn incr_right(self) -> Self {
+                let half = <$half>::BITS;
+                let right = (self as $half).wrapping_add(1);
+                ((self >> half) << half) | right as $block
+            }
+
+            fn with_msb(self, set: bool) -> Self {
+                let msb = 1 << (<$block>::BITS - 1);
+                if set {
+                    self | msb
+                } else {
+                    self & !msb
+                }
+            }
+
+            fn lengths(associated: u64, data: u64) -> Self {
+                ((associated as $half as $block) << <$half>::BITS) | data as $half as $block
+            }
+        }
+    };
+}
+
+mgm_block!(u64, u32, 0x1b);
+mgm_block!(u128, u64, 0x87);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TagMismatch;
+
+impl fmt::Display for TagMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("authentication tag doesn't match")
+    }
+}
+
+impl std::error::Error for TagMismatch {}
+
+impl From<TagMismatch> for std::io::Error {
+    fn from(value: TagMismatch) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, value)
+    }
+}
+
+/// Multilinear Galois Mode of RFC 9058 over a block cipher `encrypt`.
+///
+/// Associated data goes first, then the data. Every call except the last one
+/// of each kind must pass a multiple of the block size.
+#[derive(Debug, Clone)]
+pub struct Mgm<B: MgmBlock, E: Fn(B) -> B> {
+    encrypt: E,
+    y: B,
+    z: B,
+    sum: B,
+    associated_len: u64,
+    data_len: u64,
+}
+
+impl<B: MgmBlock, E: Fn(B) -> B> Mgm<B, E> {
+    /// The most significant bit of the `nonce` is ignored
+    pub fn new(encrypt: E, nonce: B) -> Self {
+        Self {
+            y: encrypt(nonce.with_msb(false)),
+            z: encrypt(nonce.with_msb(true)),
+            encrypt,
+            sum: B::default(),
+            associated_len: 0,
+            data_len: 0,
+        }
+    }
+
+    fn absorb(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(B::SIZE) {
+            let h = (self.encrypt)(self.z);
+            self.sum = self.sum ^ h.gf_mul(B::from_bytes(chunk));
+            self.z = self.z.incr_left();
+        }
+    }
+
+    pub fn associated_data(&mut self, bytes: &[u8]) {
+        assert_eq!(self.data_len, 0, "associated data must go before the data");
+        self.absorb(bytes);
+        self.associated_len += bytes.len() as u64;
+    }
+
+    /// Xors the data with the gamma, without authenticating it
+    pub fn keystream(&mut self, data: &mut [u8]) {
+        for chunk in data.chunks_mut(B::SIZE) {
+            let gamma = (self.encrypt)(self.y);
+            (B::from_bytes(chunk) ^ gamma).write_bytes(chunk);
+            self.y = self.y.incr_right();
+        }
+    }
+
+    /// Authenticates the ciphertext without decrypting it
+    pub fn authenticate(&mut self, ciphertext: &[u8]) {
+        self.absorb(ciphertext);
+        self.data_len += ciphertext.len() as u64;
+    }
+
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        self.keystream(data);
+        self.authenticate(data);
+    }
+
+    /// The plaintext can't be trusted until the tag is verified
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        self.authenticate(data);
+        self.keystream(data);
+    }
+
+    /// Returns the first `len` bytes of the tag
+    pub fn finish(mut self, len: usize) -> Vec<u8> {
+        assert!((1..=B::SIZE).contains(&len), "invalid tag length");
+
+        let lengths = B::lengths(self.associated_len * 8, self.data_len * 8);
+        let h = (self.encrypt)(self.z);
+        self.sum = self.sum ^ h.gf_mul(lengths);
+
+        let mut tag = vec![0; len];
+        (self.encrypt)(self.sum).write_bytes(&mut tag);
+        tag
+    }
+
+    pub fn verify(self, tag: &[u8]) -> Result<(), TagMismatch> {
+        if tag.is_empty() || tag.len() > B::SIZE {
+            return Err(TagMismatch);
+        }
+
+        let expected = self.finish(tag.len());
+        let diff = expected
+            .iter()
+            .zip(tag)
+            .fold(0, |acc, (a, b)| acc | (a ^ b));
+
+        if diff == 0 {
+            Ok(())
+        } else {
+            Err(TagMismatch)
+        }
+    }
+}
+
+/// Encrypts `data` in place and returns the tag of `tag_len` bytes
+pub fn mgm_seal<B: MgmBlock>(
+    encrypt: impl Fn(B) -> B,
+    nonce: B,
+    associated: &[u8],
+    data: &mut [u8],
+    tag_len: usize,
+) -> Vec<u8> {
+    let mut mgm = Mgm::new(encrypt, nonce);
+    mgm.associated_data(associated);
+    mgm.encrypt(data);
+    mgm.finish(tag_len)
+}
+
+/// Checks the tag and only then decrypts `data` in place
+pub fn mgm_open<B: MgmBlock>(
+    encrypt: impl Fn(B) -> B + Clone,
+    nonce: B,
+    associated: &[u8],
+    data: &mut [u8],
+    tag: &[u8],
+) -> Result<(), TagMismatch> {
+    let mut mgm = Mgm::new(encrypt.clone(), nonce);
+    mgm.associated_data(associated);
+    mgm.authenticate(data);
+    mgm.verify(tag)?;
+
+    Mgm::new(encrypt, nonce).keystream(data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Kuznyechik, Magma};
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn kuznyechik() -> impl Fn(u128) -> u128 + Clone {
+        let key = from_hex("8899aabbccddeeff0011223344556677fedcba98765432100123456789abcdef");
+        let cipher = Kuznyechik::new(&key.try_into().unwrap());
+        move |block| cipher.encrypt(block)
+    }
+
+    fn magma() -> impl Fn(u64) -> u64 + Clone {
+        let key = from_hex("ffeeddccbbaa99887766554433221100f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff");
+        let magma = Magma::new(&key.try_into().unwrap());
+        move |block| crate::simple_swap_with(block, magma.key(), &Magma::PARAMS)
+    }
+
+    #[test]
+    fn kuznyechik_vector() {
+        // RFC 9058 appendix A.1
+        let nonce = 0x1122334455667700ffeeddccbbaa9988;
+        let associated = from_hex(
+            "0202020202020202010101010101010104040404040404040303030303030303ea0505050505050505",
+        );
+        let plain = from_hex(
+            "1122334455667700ffeeddccbbaa998800112233445566778899aabbcceeff0a\
+             112233445566778899aabbcceeff0a002233445566778899aabbcceeff0a0011aabbcc",
+        );
+
+        let mut data = plain.clone();
+        let tag = mgm_seal(kuznyechik(), nonce, &associated, &mut data, 16);
+
+        assert_eq!(
+            data,
+            from_hex(
+                "a9757b8147956e9055b8a33de89f42fc8075d2212bf9fd5bd3f7069aadc16b39\
+                 497ab15915a6ba85936b5d0ea9f6851cc60c14d4d3f883d0ab94420695c76deb2c7552"
+            )
+        );
+        assert_eq!(tag, from_hex("cf5d656f40c34f5c46e8bb0e29fcdb4c"));
+
+        mgm_open(kuznyechik(), nonce, &associated, &mut data, &tag).unwrap();
+        assert_eq!(data, plain);
+    }
+
+    #[test]
+    fn magma_vector() {
+        // RFC 9058 appendix A.2
+        let nonce = 0x12def06b3c130a59;
+        let associated = from_hex(
+            "01010101010101010202020202020202030303030303030304040404040404040505050505050505ea",
+        );
+        let plain = from_hex(
+            "ffeeddccbbaa998811223344556677008899aabbcceeff0a0011223344556677\
+             99aabbcceeff0a001122334455667788aabbcceeff0a00112233445566778899aabbcc",
+        );
+
+        let mut data = plain.clone();
+        let tag = mgm_seal(magma(), nonce, &associated, &mut data, 8);
+
+        assert_eq!(
+            data,
+            from_hex(
+                "c795066c5f9ea03b85113342459185ae1f2e00d6bf2b785d940470b8bb9c8e7d\
+                 9a5dd3731f7ddc70ec27cb0ace6fa57670f65c646abb75d547aa37c3bcb5c34e03bb9c"
+            )
+        );
+        assert_eq!(tag, from_hex("a7928069aa10fd10"));
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let plain = b"sixteen byte msg and a tail";
+        let mut data = *plain;
+        let tag = mgm_seal(magma(), 42, b"header", &mut data, 8);
+
+        let mut changed = data;
+        changed[20] ^= 1;
+        assert_eq!(
+            mgm_open(magma(), 42, b"header", &mut changed, &tag),
+            Err(TagMismatch)
+        );
+        // The data is left encrypted when the tag fails
+        assert_eq!(changed[..20], data[..20]);
+
+        let mut copy = data;
+        assert!(mgm_open(magma(), 42, b"heade", &mut copy, &tag).is_err());
+        assert!(mgm_open(magma(), 43, b"header", &mut copy, &tag).is_err());
+
+        mgm_open(magma(), 42, b"header", &mut data, &tag[..4]).unwrap();
+        assert_eq!(&data, plain);
+    }
+
+    #[test]
+    fn split_calls() {
+        let mut whole = [7u8; 45];
+        let tag = mgm_seal(kuznyechik(), 1, &[1; 20], &mut whole, 16);
+
+        let mut parts = [7u8; 45];
+        let mut mgm = Mgm::new(kuznyechik(), 1);
+        mgm.associated_data(&[1; 16]);
+        mgm.associated_data(&[1; 4]);
+        let (head, tail) = parts.split_at_mut(32);
+        mgm.encrypt(head);
+        mgm.encrypt(tail);
+
+        assert_eq!(whole, parts);
+        assert_eq!(mgm.finish(16), tag);
+    }
+}
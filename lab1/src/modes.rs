@@ -0,0 +1,184 @@
+//! Modes of operation for any `BlockCipher`, blocks are numbers
+//! in the byte order of the cipher
+
+use super::block_cipher::*;
+
+/// Counter values encrypted at once
+const COUNTER_BATCH: usize = 64;
+
+/// Simple replacement: every block is encrypted on its own
+pub fn simple_replacement<C: BlockCipher>(cipher: &C, input: &mut [C::Block]) {
+    cipher.encrypt_blocks(input)
+}
+
+pub fn simple_replacement_decrypt<C: BlockCipher>(cipher: &C, input: &mut [C::Block]) {
+    cipher.decrypt_blocks(input)
+}
+
+/// Counter mode of GOST R 34.13-2015: xors every block with the encrypted counter
+/// and increments it. Returns the counter for the next block.
+pub fn counter<C: BlockCipher>(cipher: &C, counter: C::Block, input: &mut [C::Block]) -> C::Block {
+    let mut counter = counter;
+    let mut gamma = [C::Block::default(); COUNTER_BATCH];
+
+    input.chunks_mut(COUNTER_BATCH).for_each(|chunk| {
+        let gamma = &mut gamma[..chunk.len()];
+        gamma.iter_mut().for_each(|gamma| {
+            *gamma = counter;
+            counter = counter.increment();
+        });
+
+        cipher.encrypt_blocks(gamma);
+        chunk
+            .iter_mut()
+            .zip(gamma)
+            .for_each(|(block, gamma)| *block = *block ^ *gamma);
+    });
+
+    counter
+}
+
+/// Cipher block chaining with a register of `register.len()` blocks.
+/// The register is updated so the next call continues the chain.
+pub fn cipher_block_chaining<C: BlockCipher>(
+    cipher: &C,
+    register: &mut [C::Block],
+    input: &mut [C::Block],
+) {
+    input.iter_mut().for_each(|block| {
+        *block = cipher.encrypt(*block ^ register[0]);
+        register.rotate_left(1);
+        *register.last_mut().unwrap() = *block;
+    });
+}
+
+pub fn cipher_block_chaining_decrypt<C: BlockCipher>(
+    cipher: &C,
+    register: &mut [C::Block],
+    input: &mut [C::Block],
+) {
+    input.iter_mut().for_each(|block| {
+        let encrypted = *block;
+        *block = cipher.decrypt(encrypted) ^ register[0];
+        register.rotate_left(1);
+        *register.last_mut().unwrap() = encrypted;
+    });
+}
+
+/// Output feedback with a register of `register.len()` blocks.
+/// Encryption and decryption are the same operation.
+pub fn output_feedback<C: BlockCipher>(
+    cipher: &C,
+    register: &mut [C::Block],
+    input: &mut [C::Block],
+) {
+    input.iter_mut().for_each(|block| {
+        let gamma = cipher.encrypt(register[0]);
+        *block = *block ^ gamma;
+        register.rotate_left(1);
+        *register.last_mut().unwrap() = gamma;
+    });
+}
+
+/// Cipher feedback of whole blocks, returns the feedback for the next call
+pub fn cipher_feedback<C: BlockCipher>(
+    cipher: &C,
+    init: C::Block,
+    input: &mut [C::Block],
+) -> C::Block {
+    let mut last = init;
+
+    input.iter_mut().for_each(|block| {
+        *block = *block ^ cipher.encrypt(last);
+        last = *block;
+    });
+
+    last
+}
+
+pub fn cipher_feedback_decrypt<C: BlockCipher>(
+    cipher: &C,
+    init: C::Block,
+    input: &mut [C::Block],
+) -> C::Block {
+    let mut last = init;
+
+    input.iter_mut().for_each(|block| {
+        let gamma = cipher.encrypt(last);
+        last = *block;
+        *block = *block ^ gamma;
+    });
+
+    last
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Xors with the key and rotates, enough to check the chaining
+    struct Toy(u64);
+
+    impl BlockCipher for Toy {
+        type Block = u64;
+        type Key = u64;
+
+        fn new(key: &u64) -> Self {
+            Self(*key)
+        }
+
+        fn encrypt(&self, block: u64) -> u64 {
+            (block ^ self.0).rotate_left(3)
+        }
+
+        fn decrypt(&self, block: u64) -> u64 {
+            block.rotate_right(3) ^ self.0
+        }
+    }
+
+    #[test]
+    fn modes_reversible() {
+        let cipher = Toy::new(&0xC0FFEE);
+        let initial = [42, 45, 42, 42, 38];
+
+        let mut copy = initial;
+        simple_replacement(&cipher, &mut copy);
+        assert_eq!(copy[0], copy[2]);
+        simple_replacement_decrypt(&cipher, &mut copy);
+        assert_eq!(copy, initial);
+
+        counter(&cipher, 7, &mut copy);
+        assert_ne!(copy[2], copy[3]);
+        counter(&cipher, 7, &mut copy);
+        assert_eq!(copy, initial);
+
+        cipher_block_chaining(&cipher, &mut [1, 2], &mut copy);
+        assert_ne!(copy[2], copy[3]);
+        cipher_block_chaining_decrypt(&cipher, &mut [1, 2], &mut copy);
+        assert_eq!(copy, initial);
+
+        output_feedback(&cipher, &mut [1, 2], &mut copy);
+        output_feedback(&cipher, &mut [1, 2], &mut copy);
+        assert_eq!(copy, initial);
+
+        cipher_feedback(&cipher, 1, &mut copy);
+        assert_ne!(copy[2], copy[3]);
+        cipher_feedback_decrypt(&cipher, 1, &mut copy);
+        assert_eq!(copy, initial);
+    }
+
+    #[test]
+    fn split_calls() {
+        let cipher = Toy::new(&0xC0FFEE);
+
+        let mut whole = [1, 2, 3, 4, 5];
+        let next = cipher_feedback(&cipher, 9, &mut whole);
+
+        let mut parts = [1, 2, 3, 4, 5];
+        let (head, tail) = parts.split_at_mut(2);
+        let middle = cipher_feedback(&cipher, 9, head);
+        assert_eq!(cipher_feedback(&cipher, middle, tail), next);
+
+        assert_eq!(whole, parts);
+    }
+}
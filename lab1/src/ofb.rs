@@ -0,0 +1,67 @@
+use super::modes::*;
+use super::simple_swap::*;
+
+/// Output feedback of GOST R 34.13-2015 with a register of `register.len()` blocks.
+/// Encryption and decryption are the same operation.
+pub fn output_feedback_mode_block(
+    register: &mut [u64],
+    input: &mut [u64],
+    key: &MagmaKey,
+    params: &Params,
+) {
+    output_feedback(
+        &Gost28147::with_params(key.clone(), *params),
+        register,
+        input,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ofb_reversible() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let params = Params::default();
+        let init = [0xBADF00D, 0xC0FFEE];
+
+        let initial = [42, 45, 38];
+        let mut copy = initial;
+
+        output_feedback_mode_block(&mut init.clone(), &mut copy, &key, &params);
+        assert_ne!(initial, copy);
+        output_feedback_mode_block(&mut init.clone(), &mut copy, &key, &params);
+        assert_eq!(initial, copy);
+    }
+
+    #[test]
+    fn gost_34_13_vector() {
+        // GOST R 34.13-2015 appendix A.2.3, m = 2n
+        let key = MagmaKey::new([
+            0xffeeddcc, 0xbbaa9988, 0x77665544, 0x33221100, 0xf0f1f2f3, 0xf4f5f6f7, 0xf8f9fafb,
+            0xfcfdfeff,
+        ]);
+        let params = Params::new(SBoxSet::TC26_Z);
+        let mut register = [0x1234567890abcdef, 0x234567890abcdef1];
+
+        let mut blocks = [
+            0x92def06b3c130a59,
+            0xdb54c704f8189d20,
+            0x4a98fb2e67a8024c,
+            0x8912409b17b57e41,
+        ];
+
+        output_feedback_mode_block(&mut register, &mut blocks, &key, &params);
+
+        assert_eq!(
+            blocks,
+            [
+                0xdb37e0e266903c83,
+                0x0d46644c1f9a089c,
+                0xa0f83062430e327e,
+                0xc824efb8bd4fdb05,
+            ]
+        );
+    }
+}
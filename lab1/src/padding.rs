@@ -0,0 +1,186 @@
+use std::{fmt, str::FromStr};
+
+/// How the last incomplete block is filled up before encryption
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    /// No padding, the data must consist of whole blocks
+    None,
+    /// Zero bytes, they are kept after decryption
+    Zero,
+    /// PKCS#7: `n` bytes of value `n`, a whole block when the data is aligned
+    #[default]
+    Pkcs7,
+    /// ISO/IEC 7816-4, procedure 2 of GOST R 34.13-2015: `0x80` followed by zeroes
+    Iso7816,
+}
+
+impl Padding {
+    pub const ALL: [Self; 4] = [Self::None, Self::Zero, Self::Pkcs7, Self::Iso7816];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Zero => "zero",
+            Self::Pkcs7 => "pkcs7",
+            Self::Iso7816 => "iso7816",
+        }
+    }
+}
+
+impl fmt::Display for Padding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Padding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|padding| padding.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.map(Self::name).into();
+                format!("unknown padding, expected one of: {}", names.join(", "))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaddingError {
+    /// The data isn't a multiple of the block size and the padding is `None`
+    Unaligned,
+    /// The last decrypted block doesn't end with a valid padding
+    Invalid,
+}
+
+impl fmt::Display for PaddingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unaligned => f.write_str("data length isn't a multiple of the block size"),
+            Self::Invalid => f.write_str("invalid padding"),
+        }
+    }
+}
+
+impl std::error::Error for PaddingError {}
+
+impl From<PaddingError> for std::io::Error {
+    fn from(value: PaddingError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, value)
+    }
+}
+
+impl Padding {
+    /// Writes the `tail` of the data followed by the padding into `block`.
+    /// Returns `false` when no final block is needed.
+    pub fn pad(self, tail: &[u8], block: &mut [u8]) -> Result<bool, PaddingError> {
+        let len = tail.len();
+        assert!(len < block.len(), "tail must be shorter than a block");
+
+        block[..len].copy_from_slice(tail);
+        let rest = &mut block[len..];
+
+        match self {
+            Self::None if len == 0 => Ok(false),
+            Self::None => Err(PaddingError::Unaligned),
+            Self::Zero => {
+                rest.fill(0);
+                Ok(len != 0)
+            }
+            Self::Pkcs7 => {
+                rest.fill(rest.len() as u8);
+                Ok(true)
+            }
+            Self::Iso7816 => {
+                rest.fill(0);
+                rest[0] = 0x80;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Returns how many bytes of the last decrypted `block` are data
+    pub fn unpad(self, block: &[u8]) -> Result<usize, PaddingError> {
+        match self {
+            Self::None | Self::Zero => Ok(block.len()),
+            Self::Pkcs7 => {
+                let n = *block.last().unwrap_or(&0) as usize;
+                let valid = (1..=block.len()).contains(&n)
+                    && block[block.len() - n..].iter().all(|&b| b as usize == n);
+
+                if valid {
+                    Ok(block.len() - n)
+                } else {
+                    Err(PaddingError::Invalid)
+                }
+            }
+            Self::Iso7816 => match block.iter().rposition(|&b| b != 0) {
+                Some(i) if block[i] == 0x80 => Ok(i),
+                _ => Err(PaddingError::Invalid),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pad_unpad() {
+        for padding in [Padding::Pkcs7, Padding::Iso7816] {
+            for len in 0..8 {
+                let tail = &b"abcdefg"[..len];
+                let mut block = [0xAA; 8];
+
+                assert_eq!(padding.pad(tail, &mut block), Ok(true));
+                assert_eq!(padding.unpad(&block), Ok(len));
+                assert_eq!(&block[..len], tail);
+            }
+        }
+    }
+
+    #[test]
+    fn pkcs7() {
+        let mut block = [0; 8];
+        Padding::Pkcs7.pad(b"abc", &mut block).unwrap();
+        assert_eq!(&block, b"abc\x05\x05\x05\x05\x05");
+
+        assert_eq!(
+            Padding::Pkcs7.unpad(b"abc\x05\x05\x04\x05\x05"),
+            Err(PaddingError::Invalid)
+        );
+        assert_eq!(
+            Padding::Pkcs7.unpad(b"abcdefg\x00"),
+            Err(PaddingError::Invalid)
+        );
+        assert_eq!(
+            Padding::Pkcs7.unpad(b"abcdefg\x09"),
+            Err(PaddingError::Invalid)
+        );
+    }
+
+    #[test]
+    fn iso7816() {
+        let mut block = [0xAA; 8];
+        Padding::Iso7816.pad(b"abc", &mut block).unwrap();
+        assert_eq!(&block, b"abc\x80\x00\x00\x00\x00");
+
+        assert_eq!(Padding::Iso7816.unpad(&[0; 8]), Err(PaddingError::Invalid));
+    }
+
+    #[test]
+    fn none_and_zero() {
+        let mut block = [0xAA; 8];
+        assert_eq!(Padding::None.pad(b"", &mut block), Ok(false));
+        assert_eq!(
+            Padding::None.pad(b"a", &mut block),
+            Err(PaddingError::Unaligned)
+        );
+        assert_eq!(Padding::Zero.pad(b"", &mut block), Ok(false));
+        assert_eq!(Padding::Zero.pad(b"a", &mut block), Ok(true));
+        assert_eq!(&block, b"a\0\0\0\0\0\0\0");
+    }
+}
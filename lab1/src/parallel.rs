@@ -0,0 +1,156 @@
+//! Block-independent modes run on several threads, the output is the same
+//! as of the single-threaded functions
+
+use std::thread;
+
+use super::block_cipher::*;
+use super::ctr::*;
+use super::ecb::*;
+use super::modes::*;
+use super::simple_swap::*;
+
+/// Parts smaller than this are not worth a thread
+const MIN_PART_BLOCKS: usize = 1024;
+
+/// Runs `f` on up to `threads` parts of `input` at once, passing it
+/// the index of the first block of the part
+pub fn for_each_part<T: Send>(input: &mut [T], threads: usize, f: impl Fn(usize, &mut [T]) + Sync) {
+    let part_len = input.len().div_ceil(threads.max(1)).max(MIN_PART_BLOCKS);
+
+    if input.len() <= part_len {
+        return f(0, input);
+    }
+
+    thread::scope(|scope| {
+        for (i, part) in input.chunks_mut(part_len).enumerate() {
+            let f = &f;
+            scope.spawn(move || f(i * part_len, part));
+        }
+    });
+}
+
+/// `counter_mode_block` on `threads` threads
+pub fn counter_mode_block_parallel(
+    counter: u64,
+    input: &mut [u64],
+    key: &MagmaKey,
+    params: &Params,
+    threads: usize,
+) -> u64 {
+    for_each_part(input, threads, |offset, part| {
+        counter_mode_block(counter_mode_skip(counter, offset as u64), part, key, params);
+    });
+
+    counter_mode_skip(counter, input.len() as u64)
+}
+
+pub fn simple_replacement_mode_block_parallel(
+    input: &mut [u64],
+    key: &MagmaKey,
+    params: &Params,
+    threads: usize,
+) {
+    for_each_part(input, threads, |_, part| {
+        simple_replacement_mode_block(part, key, params)
+    });
+}
+
+pub fn simple_replacement_mode_block_decrypt_parallel(
+    input: &mut [u64],
+    key: &MagmaKey,
+    params: &Params,
+    threads: usize,
+) {
+    for_each_part(input, threads, |_, part| {
+        simple_replacement_mode_block_decrypt(part, key, params)
+    });
+}
+
+/// `counter` of GOST R 34.13-2015 on `threads` threads
+pub fn counter_parallel<C: BlockCipher + Sync>(
+    cipher: &C,
+    counter: C::Block,
+    input: &mut [C::Block],
+    threads: usize,
+) -> C::Block {
+    for_each_part(input, threads, |offset, part| {
+        crate::modes::counter(cipher, counter.advance(offset as u64), part);
+    });
+
+    counter.advance(input.len() as u64)
+}
+
+pub fn simple_replacement_parallel<C: BlockCipher + Sync>(
+    cipher: &C,
+    input: &mut [C::Block],
+    threads: usize,
+) {
+    for_each_part(input, threads, |_, part| simple_replacement(cipher, part));
+}
+
+pub fn simple_replacement_decrypt_parallel<C: BlockCipher + Sync>(
+    cipher: &C,
+    input: &mut [C::Block],
+    threads: usize,
+) {
+    for_each_part(input, threads, |_, part| {
+        simple_replacement_decrypt(cipher, part)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Kuznyechik;
+
+    #[test]
+    fn gost_same_as_single_thread() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let params = Params::default();
+        let initial: Vec<u64> = (0..5000).collect();
+
+        let mut single = initial.clone();
+        let next = counter_mode_block(0xBADF00D, &mut single, &key, &params);
+
+        let mut parallel = initial.clone();
+        let (head, tail) = parallel.split_at_mut(3001);
+        let middle = counter_mode_block_parallel(0xBADF00D, head, &key, &params, 3);
+        assert_eq!(
+            counter_mode_block_parallel(middle, tail, &key, &params, 4),
+            next
+        );
+        assert_eq!(parallel, single);
+
+        simple_replacement_mode_block(&mut single, &key, &params);
+        simple_replacement_mode_block_parallel(&mut parallel, &key, &params, 5);
+        assert_eq!(parallel, single);
+
+        simple_replacement_mode_block_decrypt_parallel(&mut parallel, &key, &params, 2);
+        simple_replacement_mode_block_decrypt(&mut single, &key, &params);
+        assert_eq!(parallel, single);
+    }
+
+    #[test]
+    fn generic_same_as_single_thread() {
+        let cipher = Kuznyechik::new(&[7; 32]);
+        let initial: Vec<u128> = (0..4321).collect();
+
+        let mut single = initial.clone();
+        let next = crate::modes::counter(&cipher, u128::MAX - 9, &mut single);
+
+        let mut parallel = initial.clone();
+        assert_eq!(
+            counter_parallel(&cipher, u128::MAX - 9, &mut parallel, 4),
+            next
+        );
+        assert_eq!(parallel, single);
+
+        simple_replacement(&cipher, &mut single);
+        simple_replacement_parallel(&cipher, &mut parallel, 3);
+        assert_eq!(parallel, single);
+
+        simple_replacement_decrypt_parallel(&cipher, &mut parallel, 3);
+        simple_replacement_decrypt(&cipher, &mut single);
+        assert_eq!(parallel, single);
+    }
+}
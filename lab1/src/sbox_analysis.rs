@@ -0,0 +1,257 @@
+//! Properties of an S-box that tell how well it resists differential
+//! and linear cryptanalysis
+
+use std::{fmt, io};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SBoxError {
+    /// The S-box must have `2^n` entries, `n` from 1 to 8
+    Length(usize),
+    /// An output that doesn't fit in the bits of the input
+    Value(u8),
+}
+
+impl fmt::Display for SBoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Length(len) => write!(
+                f,
+                "an S-box must have 2, 4, ..., 256 entries, this one has {len}"
+            ),
+            Self::Value(value) => write!(f, "S-box value {value} is too large for its size"),
+        }
+    }
+}
+
+impl std::error::Error for SBoxError {}
+
+impl From<SBoxError> for io::Error {
+    fn from(value: SBoxError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, value.to_string())
+    }
+}
+
+/// What the analysis found for an S-box of `2^bits` entries of `bits` bits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SBoxReport {
+    pub bits: u32,
+    /// Whether the S-box is a permutation
+    pub bijective: bool,
+    /// `ddt[a][b]` is the number of inputs `x` with `S(x) ^ S(x ^ a) == b`
+    pub ddt: Vec<Vec<u32>>,
+    /// `lat[a][b]` is the number of inputs `x` with `a·x == b·S(x)` minus half of them
+    pub lat: Vec<Vec<i32>>,
+    /// The largest DDT entry for a non-zero input difference
+    pub differential_uniformity: u32,
+    /// The smallest distance from a non-zero component `b·S` to the affine functions
+    pub nonlinearity: u32,
+    /// The highest degree of the output bits as polynomials of the input bits
+    pub algebraic_degree: u32,
+    pub fixed_points: Vec<u8>,
+}
+
+/// Turns the values of a function into its Walsh–Hadamard spectrum in place
+fn walsh_hadamard(values: &mut [i32]) {
+    let mut half = 1;
+
+    while half < values.len() {
+        for pair in values.chunks_mut(half * 2) {
+            let (low, high) = pair.split_at_mut(half);
+            low.iter_mut().zip(high).for_each(|(u, v)| {
+                (*u, *v) = (*u + *v, *u - *v);
+            });
+        }
+
+        half *= 2;
+    }
+}
+
+/// Degree of the output bit `bit` from its algebraic normal form
+fn output_degree(sbox: &[u8], bit: u32) -> u32 {
+    let mut coefficients: Vec<u8> = sbox.iter().map(|y| y >> bit & 1).collect();
+
+    let mut step = 1;
+    while step < sbox.len() {
+        for x in (0..sbox.len()).filter(|x| x & step != 0) {
+            coefficients[x] ^= coefficients[x ^ step];
+        }
+        step *= 2;
+    }
+
+    (0..sbox.len())
+        .filter(|&s| coefficients[s] == 1)
+        .map(|s| s.count_ones())
+        .max()
+        .unwrap_or(0)
+}
+
+impl SBoxReport {
+    /// `sbox[x]` is the output for input `x`
+    pub fn new(sbox: &[u8]) -> Result<Self, SBoxError> {
+        let size = sbox.len();
+
+        if !size.is_power_of_two() || !(2..=256).contains(&size) {
+            return Err(SBoxError::Length(size));
+        }
+
+        if let Some(&value) = sbox.iter().find(|&&y| y as usize >= size) {
+            return Err(SBoxError::Value(value));
+        }
+
+        let bits = size.trailing_zeros();
+
+        let mut ddt = vec![vec![0; size]; size];
+        for (a, row) in ddt.iter_mut().enumerate() {
+            for x in 0..size {
+                row[(sbox[x] ^ sbox[x ^ a]) as usize] += 1;
+            }
+        }
+
+        let mut lat = vec![vec![0; size]; size];
+        for b in 0..size {
+            // The spectrum of the component `b·S` gives column `b`
+            let mut walsh: Vec<i32> = sbox
+                .iter()
+                .map(|&y| 1 - 2 * ((y as usize & b).count_ones() % 2) as i32)
+                .collect();
+            walsh_hadamard(&mut walsh);
+
+            lat.iter_mut()
+                .zip(walsh)
+                .for_each(|(row, walsh)| row[b] = walsh / 2);
+        }
+
+        let differential_uniformity = ddt[1..].iter().flatten().copied().max().unwrap();
+
+        let largest_bias = lat
+            .iter()
+            .flat_map(|row| &row[1..])
+            .map(|bias| bias.unsigned_abs())
+            .max()
+            .unwrap();
+
+        let mut sorted = sbox.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+
+        Ok(Self {
+            bits,
+            bijective: sorted.len() == size,
+            ddt,
+            lat,
+            differential_uniformity,
+            nonlinearity: size as u32 / 2 - largest_bias,
+            algebraic_degree: (0..bits).map(|bit| output_degree(sbox, bit)).max().unwrap(),
+            fixed_points: (0..size)
+                .filter(|&x| sbox[x] as usize == x)
+                .map(|x| x as u8)
+                .collect(),
+        })
+    }
+
+    /// Writes the DDT and the LAT, a row per input difference or mask
+    pub fn write_tables(&self, output: &mut impl io::Write) -> io::Result<()> {
+        let width = (1 << self.bits).to_string().len() + 2;
+
+        writeln!(output, "difference distribution table:")?;
+        for row in &self.ddt {
+            row.iter().try_for_each(|n| write!(output, "{n:>width$}"))?;
+            writeln!(output)?;
+        }
+
+        writeln!(output, "linear approximation table:")?;
+        for row in &self.lat {
+            row.iter().try_for_each(|n| write!(output, "{n:>width$}"))?;
+            writeln!(output)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SBoxReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.bijective {
+            "permutation"
+        } else {
+            "not a permutation"
+        };
+
+        writeln!(f, "{}-bit S-box, {kind}", self.bits)?;
+        writeln!(
+            f,
+            "differential uniformity: {}",
+            self.differential_uniformity
+        )?;
+        writeln!(f, "nonlinearity: {}", self.nonlinearity)?;
+        writeln!(f, "algebraic degree: {}", self.algebraic_degree)?;
+
+        write!(f, "fixed points:")?;
+        if self.fixed_points.is_empty() {
+            return write!(f, " none");
+        }
+        self.fixed_points
+            .iter()
+            .try_for_each(|x| write!(f, " {x:#04x}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Kuznyechik, SBoxSet};
+
+    #[test]
+    fn present_sbox() {
+        // The S-box of PRESENT, optimal among 4-bit ones
+        let report = SBoxReport::new(&[
+            0xc, 0x5, 0x6, 0xb, 0x9, 0x0, 0xa, 0xd, 0x3, 0xe, 0xf, 0x8, 0x4, 0x7, 0x1, 0x2,
+        ])
+        .unwrap();
+
+        assert!(report.bijective);
+        assert_eq!(report.differential_uniformity, 4);
+        assert_eq!(report.nonlinearity, 4);
+        assert_eq!(report.algebraic_degree, 3);
+        assert!(report.fixed_points.is_empty());
+        assert_eq!(report.ddt[0][0], 16);
+        assert_eq!(report.lat[0][0], 8);
+        assert!(report.ddt.iter().all(|row| row.iter().sum::<u32>() == 16));
+    }
+
+    #[test]
+    fn identity_is_linear() {
+        let identity: Vec<u8> = (0..16).collect();
+        let report = SBoxReport::new(&identity).unwrap();
+
+        assert_eq!(report.differential_uniformity, 16);
+        assert_eq!(report.nonlinearity, 0);
+        assert_eq!(report.algebraic_degree, 1);
+        assert_eq!(report.fixed_points, identity);
+    }
+
+    #[test]
+    fn kuznyechik_sbox() {
+        let report = SBoxReport::new(&Kuznyechik::S_BOX).unwrap();
+
+        assert!(report.bijective);
+        assert_eq!(report.differential_uniformity, 8);
+        assert_eq!(report.nonlinearity, 100);
+        assert_eq!(report.algebraic_degree, 7);
+    }
+
+    #[test]
+    fn gost_tables_are_permutations() {
+        for sbox in SBoxSet::ALL {
+            for table in sbox.tables {
+                assert!(SBoxReport::new(&table).unwrap().bijective);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects() {
+        assert_eq!(SBoxReport::new(&[0; 12]), Err(SBoxError::Length(12)));
+        assert_eq!(SBoxReport::new(&[0, 1, 2, 4]), Err(SBoxError::Value(4)));
+    }
+}
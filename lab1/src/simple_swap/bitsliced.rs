@@ -0,0 +1,183 @@
+//! Bitsliced rounds: bit `j` of 64 blocks is kept in a `u64`, bit `k` of it for block `k`,
+//! so the rounds are boolean operations only and neither memory accesses nor timing
+//! depend on the key or the data. The S-boxes are public, every output bit of them
+//! is computed from its algebraic normal form.
+
+use super::{Layout, Params};
+
+/// Blocks processed at once
+pub const BITSLICE_LANES: usize = 64;
+
+type Slices = [u64; 32];
+
+/// Turns the 64 numbers into their bit slices and back, the transposition of a bit matrix
+fn transpose(m: &mut [u64; 64]) {
+    let mut j = 32;
+    let mut mask = 0x0000_0000_ffff_ffff_u64;
+
+    while j != 0 {
+        for k in (0..64).filter(|k| k & j == 0) {
+            let t = ((m[k] >> j) ^ m[k | j]) & mask;
+            m[k] ^= t << j;
+            m[k | j] ^= t;
+        }
+
+        j >>= 1;
+        mask ^= mask << j;
+    }
+}
+
+/// Bit `s` of `anf[n][o]` is the coefficient of the product of the input bits in `s`
+/// in output bit `o` of the S-box that replaces nibble `n`
+fn algebraic_normal_form(params: &Params) -> [[u16; 4]; 8] {
+    params.nibble_tables().map(|table| {
+        std::array::from_fn(|o| {
+            let mut coefficients: [u8; 16] = std::array::from_fn(|x| table[x] >> o & 1);
+
+            for bit in [1, 2, 4, 8] {
+                for x in (0..16).filter(|x| x & bit != 0) {
+                    coefficients[x] ^= coefficients[x ^ bit];
+                }
+            }
+
+            coefficients
+                .iter()
+                .enumerate()
+                .fold(0, |anf, (s, &c)| anf | (c as u16) << s)
+        })
+    })
+}
+
+/// The round function: addition of the subkey, substitution and rotation by 11 bits
+fn f(n1: &Slices, subkey: &Slices, anf: &[[u16; 4]; 8]) -> Slices {
+    let mut sum = [0; 32];
+    let mut carry = 0;
+
+    for j in 0..32 {
+        let half = n1[j] ^ subkey[j];
+        sum[j] = half ^ carry;
+        carry = (n1[j] & subkey[j]) | (carry & half);
+    }
+
+    let mut output = [0; 32];
+
+    for (n, anf) in anf.iter().enumerate() {
+        let x = &sum[n * 4..n * 4 + 4];
+
+        // Products of every subset of the input bits, the empty one is all ones
+        let mut products = [!0; 16];
+        for (bit, &x) in x.iter().enumerate() {
+            for s in 0..1 << bit {
+                products[s | 1 << bit] = products[s] & x;
+            }
+        }
+
+        for (o, anf) in anf.iter().enumerate() {
+            output[(n * 4 + o + 11) % 32] =
+                products.iter().enumerate().fold(0, |bit, (s, product)| {
+                    bit ^ product & 0u64.wrapping_sub((anf >> s & 1) as u64)
+                });
+        }
+    }
+
+    output
+}
+
+/// Slices of a subkey shared by all blocks
+fn shared_subkey(subkey: u32) -> Slices {
+    std::array::from_fn(|j| 0u64.wrapping_sub((subkey >> j & 1) as u64))
+}
+
+/// Runs `count` rounds of `simple_swap_core` on up to 64 blocks,
+/// `subkey(i)` gives the slices of the subkeys of round `i`
+fn rounds(blocks: &mut [u64], params: &Params, count: usize, subkey: impl Fn(usize) -> Slices) {
+    assert!(
+        blocks.len() <= BITSLICE_LANES,
+        "too many blocks for a batch"
+    );
+
+    let mut m = [0; 64];
+    m[..blocks.len()].copy_from_slice(blocks);
+    transpose(&mut m);
+
+    let (low, high) = m.split_at_mut(32);
+    let (low, high): (&mut Slices, &mut Slices) =
+        (low.try_into().unwrap(), high.try_into().unwrap());
+
+    let (n1, n2) = match params.layout {
+        Layout::Standard => (low, high),
+        Layout::Legacy => (high, low),
+    };
+
+    let anf = algebraic_normal_form(params);
+
+    // The halves take turns instead of being swapped after every round
+    for i in 0..count {
+        if i % 2 == 0 {
+            let round = f(n1, &subkey(i), &anf);
+            n2.iter_mut().zip(round).for_each(|(n2, f)| *n2 ^= f);
+        } else {
+            let round = f(n2, &subkey(i), &anf);
+            n1.iter_mut().zip(round).for_each(|(n1, f)| *n1 ^= f);
+        }
+    }
+
+    // The last round has no swap, so after an even number of rounds
+    // the halves end up swapped once
+    if count.is_multiple_of(2) {
+        std::mem::swap(n1, n2);
+    }
+
+    transpose(&mut m);
+    blocks.copy_from_slice(&m[..blocks.len()]);
+}
+
+/// Runs a round per subkey on any number of blocks, 64 at a time
+pub(super) fn simple_swap_bitsliced(blocks: &mut [u64], subkeys: &[u32], params: &Params) {
+    let subkeys: Vec<_> = subkeys.iter().copied().map(shared_subkey).collect();
+
+    blocks
+        .chunks_mut(BITSLICE_LANES)
+        .for_each(|batch| rounds(batch, params, subkeys.len(), |i| subkeys[i]));
+}
+
+/// Up to 64 blocks, each with its own subkeys
+pub(super) fn simple_swap_bitsliced_keys(
+    blocks: &mut [u64],
+    subkeys: &[&[u32; 32]],
+    params: &Params,
+) {
+    assert_eq!(blocks.len(), subkeys.len(), "every block needs its key");
+
+    rounds(blocks, params, 32, |i| {
+        let mut m = [0; 64];
+        m.iter_mut()
+            .zip(subkeys)
+            .for_each(|(m, subkeys)| *m = subkeys[i] as u64);
+        transpose(&mut m);
+
+        m[..32].try_into().unwrap()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transpose_moves_bits() {
+        let initial: [u64; 64] =
+            std::array::from_fn(|i| (i as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15));
+        let mut m = initial;
+        transpose(&mut m);
+
+        for (i, row) in m.iter().enumerate() {
+            for (j, column) in initial.iter().enumerate() {
+                assert_eq!(row >> j & 1, column >> i & 1);
+            }
+        }
+
+        transpose(&mut m);
+        assert_eq!(m, initial);
+    }
+}
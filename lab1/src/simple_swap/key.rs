@@ -0,0 +1,72 @@
+use std::fmt;
+
+use zeroize::Zeroize;
+
+/// GOST 28147-89 key expanded into the round keys of both directions,
+/// wiped when dropped
+#[derive(Clone, PartialEq, Eq)]
+pub struct MagmaKey {
+    pub(super) encrypt: [u32; 32],
+    pub(super) decrypt: [u32; 32],
+}
+
+impl MagmaKey {
+    /// Takes the key words K1..K8
+    pub fn new(key: [u32; 8]) -> Self {
+        let mut encrypt = [0; 32];
+        for (i, subkey) in encrypt.iter_mut().enumerate() {
+            *subkey = if i < 24 { key[i % 8] } else { key[7 - i % 8] };
+        }
+
+        let mut decrypt = encrypt;
+        decrypt.reverse();
+
+        Self { encrypt, decrypt }
+    }
+
+    /// The key words K1..K8 the key was made of
+    pub fn words(&self) -> [u32; 8] {
+        self.encrypt[..8].try_into().unwrap()
+    }
+
+    /// The subkey of encryption round `i`, counted from zero
+    pub fn round_key(&self, i: usize) -> u32 {
+        self.encrypt[i]
+    }
+}
+
+impl From<[u32; 8]> for MagmaKey {
+    fn from(key: [u32; 8]) -> Self {
+        Self::new(key)
+    }
+}
+
+impl Drop for MagmaKey {
+    fn drop(&mut self) {
+        self.encrypt.zeroize();
+        self.decrypt.zeroize();
+    }
+}
+
+/// Keeps the key out of logs
+impl fmt::Debug for MagmaKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MagmaKey").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_key_order() {
+        let key = MagmaKey::new([1, 2, 3, 4, 5, 6, 7, 8]);
+
+        assert_eq!(key.words(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(key.encrypt[16..24], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(key.encrypt[24..], [8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(key.decrypt[..8], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(key.decrypt[8..16], [8, 7, 6, 5, 4, 3, 2, 1]);
+    }
+}
@@ -1,66 +1,489 @@
+use std::{fmt, str::FromStr};
+
+mod bitsliced;
+mod key;
 mod sbox;
-use sbox::*;
+pub use bitsliced::BITSLICE_LANES;
+pub use key::MagmaKey;
+pub use sbox::SBoxSet;
 
-fn f(input: u32, subkey: u32) -> u32 {
-    let added = input.wrapping_add(subkey);
-    let mixed = substitute(added, S_BOX);
-    mixed.rotate_left(11)
+/// Selects how the cipher core arranges the substitution and the block halves
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// GOST 28147-89: nibble `i` goes through S-box `i`
+    /// and the low half of the block is N1
+    #[default]
+    Standard,
+    /// Layout of the first `lab1` versions, needed to decrypt their files
+    Legacy,
 }
 
-fn simple_swap_core(input: u64, key: [u32; 8], decrypt: bool) -> u64 {
-    let reversed_key = {
-        let mut k = key;
-        k.reverse();
-        k
-    };
+/// How bytes are packed into `u64` blocks by the functions working on bytes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// GOST 28147-89 as implemented by `lab1`: the first byte is the lowest one
+    #[default]
+    Little,
+    /// GOST R 34.12-2015: the first byte is the highest one
+    Big,
+}
 
-    let central_key = if decrypt { reversed_key } else { key };
+impl ByteOrder {
+    pub fn block(self, bytes: [u8; 8]) -> u64 {
+        match self {
+            Self::Little => u64::from_le_bytes(bytes),
+            Self::Big => u64::from_be_bytes(bytes),
+        }
+    }
 
-    let mut reg_a = (input >> 32) as u32;
-    let mut reg_b = input as u32;
+    pub fn bytes(self, block: u64) -> [u8; 8] {
+        match self {
+            Self::Little => block.to_le_bytes(),
+            Self::Big => block.to_be_bytes(),
+        }
+    }
 
-    let mut iteration = |subkey: u32| {
-        let round = reg_b ^ f(reg_a, subkey);
-        reg_b = reg_a;
-        reg_a = round;
-    };
+    pub fn word(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Self::Little => u32::from_le_bytes(bytes),
+            Self::Big => u32::from_be_bytes(bytes),
+        }
+    }
+
+    /// Converts blocks read from memory as native `u64`s into numbers and back,
+    /// the conversion is its own inverse
+    pub fn convert(self, blocks: &mut [u64]) {
+        blocks
+            .iter_mut()
+            .for_each(|block| *block = self.block(block.to_ne_bytes()));
+    }
+}
+
+/// How the rounds are computed, the output is the same
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Bitsliced for batches of blocks, as in ECB, CTR and the GOST R 34.11-94 hash,
+    /// and tables for the single blocks of the feedback modes
+    #[default]
+    Auto,
+    /// Table lookups, fast for single blocks but the memory accesses depend on the
+    /// key and the data
+    Table,
+    /// Boolean operations on 64 blocks at once, in constant time
+    Bitsliced,
+}
+
+impl Backend {
+    pub const ALL: [Self; 3] = [Self::Auto, Self::Table, Self::Bitsliced];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Table => "table",
+            Self::Bitsliced => "bitsliced",
+        }
+    }
+
+    /// Whether a single block goes through the bitsliced rounds
+    fn bitsliced_block(self) -> bool {
+        self == Self::Bitsliced
+    }
+
+    /// Whether a batch of blocks goes through the bitsliced rounds
+    fn bitsliced_batch(self) -> bool {
+        self != Self::Table
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|backend| backend.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.map(Self::name).into();
+                format!("unknown backend, expected one of: {}", names.join(", "))
+            })
+    }
+}
+
+/// Cipher parameters chosen together with a key
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    pub sbox: SBoxSet,
+    pub layout: Layout,
+    pub order: ByteOrder,
+    pub backend: Backend,
+}
 
-    for subkey in key {
-        iteration(subkey);
+impl Params {
+    /// The tables nibbles `0..8` of the round function go through in this layout
+    pub fn nibble_tables(&self) -> [[u8; 16]; 8] {
+        std::array::from_fn(|n| match self.layout {
+            Layout::Standard => self.sbox.tables[n],
+            // Tables 0, 2, 4, 6 for low nibbles and 0, 1, 2, 3 for high ones
+            Layout::Legacy if n % 2 == 0 => self.sbox.tables[n],
+            Layout::Legacy => self.sbox.tables[n / 2],
+        })
     }
 
-    for _ in 0..2 {
-        for subkey in central_key {
-            iteration(subkey);
+    pub const fn new(sbox: SBoxSet) -> Self {
+        Self {
+            sbox,
+            layout: Layout::Standard,
+            order: ByteOrder::Little,
+            backend: Backend::Auto,
         }
     }
+}
+
+fn f(input: u32, subkey: u32, params: &Params) -> u32 {
+    let added = input.wrapping_add(subkey);
+    let tables = match params.layout {
+        Layout::Standard => &params.sbox.round_tables.standard,
+        Layout::Legacy => &params.sbox.round_tables.legacy,
+    };
+
+    tables[0][added as u8 as usize]
+        ^ tables[1][(added >> 8) as u8 as usize]
+        ^ tables[2][(added >> 16) as u8 as usize]
+        ^ tables[3][(added >> 24) as u8 as usize]
+}
+
+/// Splits the block into the registers N1 and N2
+pub(crate) fn split_block(input: u64, layout: Layout) -> (u32, u32) {
+    match layout {
+        Layout::Standard => (input as u32, (input >> 32) as u32),
+        Layout::Legacy => ((input >> 32) as u32, input as u32),
+    }
+}
+
+pub(crate) fn join_block(n1: u32, n2: u32, layout: Layout) -> u64 {
+    match layout {
+        Layout::Standard => ((n2 as u64) << 32) | n1 as u64,
+        Layout::Legacy => ((n1 as u64) << 32) | n2 as u64,
+    }
+}
+
+/// Runs a round per subkey, the last one without swapping the halves
+fn simple_swap_core(input: u64, subkeys: &[u32], params: &Params) -> u64 {
+    assert!(!subkeys.is_empty(), "at least a round is needed");
+
+    if params.backend.bitsliced_block() {
+        let mut blocks = [input];
+        bitsliced::simple_swap_bitsliced(&mut blocks, subkeys, params);
+        return blocks[0];
+    }
+
+    let (mut reg_a, mut reg_b) = split_block(input, params.layout);
+
+    let (&last, subkeys) = subkeys.split_last().unwrap();
+
+    for &subkey in subkeys {
+        let round = reg_b ^ f(reg_a, subkey, params);
+        reg_b = reg_a;
+        reg_a = round;
+    }
+
+    reg_b ^= f(reg_a, last, params);
+
+    join_block(reg_a, reg_b, params.layout)
+}
+
+/// The first 16 rounds of encryption, the step of the imitovstavka
+pub fn simple_swap_16_rounds(input: u64, key: &MagmaKey, params: &Params) -> u64 {
+    // The 16th round swaps the halves, unlike the last round of the core
+    let (reg_a, reg_b) = split_block(
+        simple_swap_core(input, &key.encrypt[..16], params),
+        params.layout,
+    );
+
+    join_block(reg_b, reg_a, params.layout)
+}
+
+/// Encryption reduced to its first `rounds` rounds, the last one without swapping the halves.
+/// With 32 rounds it is `simple_swap_with`.
+pub fn simple_swap_rounds(input: u64, key: &MagmaKey, rounds: usize, params: &Params) -> u64 {
+    assert!(
+        (1..=32).contains(&rounds),
+        "GOST 28147-89 has 1 to 32 rounds"
+    );
+    simple_swap_core(input, &key.encrypt[..rounds], params)
+}
+
+pub fn simple_swap(input: u64, key: &MagmaKey) -> u64 {
+    simple_swap_core(input, &key.encrypt, &Params::default())
+}
+
+pub fn simple_swap_decrypt(input: u64, key: &MagmaKey) -> u64 {
+    simple_swap_core(input, &key.decrypt, &Params::default())
+}
+
+pub fn simple_swap_with(input: u64, key: &MagmaKey, params: &Params) -> u64 {
+    simple_swap_core(input, &key.encrypt, params)
+}
 
-    for &subkey in &reversed_key[..7] {
-        iteration(subkey)
+pub fn simple_swap_decrypt_with(input: u64, key: &MagmaKey, params: &Params) -> u64 {
+    simple_swap_core(input, &key.decrypt, params)
+}
+
+/// `simple_swap_core` on every block, with the backend chosen by `params`
+fn simple_swap_blocks_core(blocks: &mut [u64], subkeys: &[u32], params: &Params) {
+    if params.backend.bitsliced_batch() {
+        bitsliced::simple_swap_bitsliced(blocks, subkeys, params);
+    } else {
+        blocks
+            .iter_mut()
+            .for_each(|block| *block = simple_swap_core(*block, subkeys, params));
     }
+}
 
-    reg_b = reg_b ^ f(reg_a, key[0]);
+pub fn simple_swap_blocks_with(blocks: &mut [u64], key: &MagmaKey, params: &Params) {
+    simple_swap_blocks_core(blocks, &key.encrypt, params)
+}
 
-    ((reg_a as u64) << 32) | reg_b as u64
+pub fn simple_swap_decrypt_blocks_with(blocks: &mut [u64], key: &MagmaKey, params: &Params) {
+    simple_swap_blocks_core(blocks, &key.decrypt, params)
 }
 
-pub fn simple_swap(input: u64, key: [u32; 8]) -> u64 {
-    simple_swap_core(input, key, false)
+/// Encrypts every block with its own key
+pub fn simple_swap_keys_with(blocks: &mut [u64], keys: &[&MagmaKey], params: &Params) {
+    assert_eq!(blocks.len(), keys.len(), "every block needs its key");
+
+    if params.backend.bitsliced_batch() {
+        blocks
+            .chunks_mut(BITSLICE_LANES)
+            .zip(keys.chunks(BITSLICE_LANES))
+            .for_each(|(blocks, keys)| {
+                let subkeys: Vec<_> = keys.iter().map(|key| &key.encrypt).collect();
+                bitsliced::simple_swap_bitsliced_keys(blocks, &subkeys, params);
+            });
+    } else {
+        blocks
+            .iter_mut()
+            .zip(keys)
+            .for_each(|(block, key)| *block = simple_swap_core(*block, &key.encrypt, params));
+    }
+}
+
+/// GOST 28147-89 with any parameters for the generic modes,
+/// blocks are numbers packed by the caller in `params.order`
+#[derive(Debug, Clone)]
+pub struct Gost28147 {
+    key: MagmaKey,
+    params: Params,
 }
 
-#[allow(unused)]
-pub fn simple_swap_decrypt(input: u64, key: [u32; 8]) -> u64 {
-    simple_swap_core(input, key, true)
+impl Gost28147 {
+    pub fn with_params(key: MagmaKey, params: Params) -> Self {
+        Self { key, params }
+    }
+}
+
+impl crate::BlockCipher for Gost28147 {
+    type Block = u64;
+    type Key = MagmaKey;
+
+    fn new(key: &MagmaKey) -> Self {
+        Self::with_params(key.clone(), Params::default())
+    }
+
+    fn encrypt(&self, block: u64) -> u64 {
+        simple_swap_core(block, &self.key.encrypt, &self.params)
+    }
+
+    fn decrypt(&self, block: u64) -> u64 {
+        simple_swap_core(block, &self.key.decrypt, &self.params)
+    }
+
+    fn encrypt_blocks(&self, blocks: &mut [u64]) {
+        simple_swap_blocks_core(blocks, &self.key.encrypt, &self.params)
+    }
+
+    fn decrypt_blocks(&self, blocks: &mut [u64]) {
+        simple_swap_blocks_core(blocks, &self.key.decrypt, &self.params)
+    }
+
+    fn byte_order(&self) -> ByteOrder {
+        self.params.order
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::sbox::{substitute, substitute_legacy};
     use super::*;
 
     #[test]
     fn simple_swap_reversible() {
-        let key = [83, 3, 6, 24, 525, 646, 233, 32];
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
         let input = 42;
-        assert_eq!(simple_swap_decrypt(simple_swap(42, key), key), input);
+        assert_eq!(simple_swap_decrypt(simple_swap(42, &key), &key), input);
+
+        for sbox in SBoxSet::ALL {
+            for layout in [Layout::Standard, Layout::Legacy] {
+                let params = Params {
+                    sbox,
+                    layout,
+                    ..Default::default()
+                };
+                let output = simple_swap_with(input, &key, &params);
+                assert_eq!(simple_swap_decrypt_with(output, &key, &params), input);
+            }
+        }
+    }
+
+    #[test]
+    fn round_tables_match_substitution() {
+        for sbox in SBoxSet::ALL {
+            for input in (0..=u32::MAX).step_by(0x10203) {
+                let standard = Params::new(sbox);
+                let legacy = Params {
+                    layout: Layout::Legacy,
+                    ..standard
+                };
+
+                let expected = substitute(input, &sbox.tables).rotate_left(11);
+                assert_eq!(f(input, 0, &standard), expected);
+
+                let expected = substitute_legacy(input, &sbox.tables).rotate_left(11);
+                assert_eq!(f(input, 0, &legacy), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn standard_known_answer() {
+        let key = MagmaKey::new(std::array::from_fn(|i| {
+            let i = i as u8 * 4;
+            u32::from_le_bytes([i, i + 1, i + 2, i + 3])
+        }));
+        let input = u64::from_le_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let output = simple_swap(input, &key);
+        assert_eq!(
+            output.to_le_bytes(),
+            [0x1b, 0xe9, 0x64, 0x89, 0xdc, 0x52, 0x8c, 0x43]
+        );
+    }
+
+    #[test]
+    fn legacy_matches_old_output() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let input = 0x0807060504030201;
+
+        let params = Params {
+            layout: Layout::Legacy,
+            ..Default::default()
+        };
+
+        let output = simple_swap_with(input, &key, &params);
+        assert_eq!(output, 0xc57016140df6aaae);
+    }
+
+    #[test]
+    fn tc26_z_known_answer() {
+        // RFC 8891 test vector, the key words and the block taken as numbers
+        let key = MagmaKey::new([
+            0xffeeddcc, 0xbbaa9988, 0x77665544, 0x33221100, 0xf0f1f2f3, 0xf4f5f6f7, 0xf8f9fafb,
+            0xfcfdfeff,
+        ]);
+        let params = Params::new(SBoxSet::TC26_Z);
+
+        let output = simple_swap_with(0xfedcba9876543210, &key, &params);
+        assert_eq!(output, 0x4ee901e5c2d8ca3d);
+    }
+
+    #[test]
+    fn reduced_rounds() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let params = Params {
+            backend: Backend::Table,
+            ..Default::default()
+        };
+        let input = 0x0807060504030201;
+
+        assert_eq!(
+            simple_swap_rounds(input, &key, 32, &params),
+            simple_swap_with(input, &key, &params)
+        );
+
+        // One round changes N2 only
+        let (n1, n2) = split_block(input, params.layout);
+        let output = simple_swap_rounds(input, &key, 1, &params);
+        assert_eq!(
+            output,
+            join_block(n1, n2 ^ f(n1, key.round_key(0), &params), params.layout)
+        );
+
+        let bitsliced = Params {
+            backend: Backend::Bitsliced,
+            ..params
+        };
+        for rounds in [1, 2, 5, 31] {
+            assert_eq!(
+                simple_swap_rounds(input, &key, rounds, &bitsliced),
+                simple_swap_rounds(input, &key, rounds, &params)
+            );
+        }
+        assert_eq!(
+            simple_swap_16_rounds(input, &key, &bitsliced),
+            simple_swap_16_rounds(input, &key, &params)
+        );
+    }
+
+    #[test]
+    fn bitsliced_matches_tables() {
+        let key = MagmaKey::new([83, 3, 6, 24, 525, 646, 233, 32]);
+        let other = MagmaKey::new([0xffeeddcc, 1, 0x80000000, 0xffffffff, 5, 6, 7, 8]);
+        let input: Vec<u64> = (0..150u64)
+            .map(|i| i.wrapping_mul(0x9e3779b97f4a7c15) ^ i << 60)
+            .collect();
+
+        for sbox in SBoxSet::ALL {
+            for layout in [Layout::Standard, Layout::Legacy] {
+                let table = Params {
+                    sbox,
+                    layout,
+                    backend: Backend::Table,
+                    ..Default::default()
+                };
+                let bitsliced = Params {
+                    backend: Backend::Bitsliced,
+                    ..table
+                };
+
+                // Full and partial batches
+                for len in [1, 3, 64, 150] {
+                    let mut expected = input[..len].to_vec();
+                    let mut blocks = expected.clone();
+
+                    simple_swap_blocks_with(&mut expected, &key, &table);
+                    simple_swap_blocks_with(&mut blocks, &key, &bitsliced);
+                    assert_eq!(blocks, expected);
+
+                    simple_swap_decrypt_blocks_with(&mut blocks, &key, &bitsliced);
+                    assert_eq!(blocks, input[..len]);
+                }
+
+                let keys: Vec<_> = (0..70)
+                    .map(|i| if i % 3 == 0 { &other } else { &key })
+                    .collect();
+                let mut expected = input[..70].to_vec();
+                let mut blocks = expected.clone();
+
+                simple_swap_keys_with(&mut expected, &keys, &table);
+                simple_swap_keys_with(&mut blocks, &keys, &bitsliced);
+                assert_eq!(blocks, expected);
+            }
+        }
     }
 }
@@ -1,21 +1,283 @@
-pub(super) static S_BOX: [[u8; 16]; 8] = [
-    [4, 10, 9, 2, 13, 8, 0, 14, 6, 11, 1, 12, 7, 15, 5, 3],
-    [14, 11, 4, 12, 6, 13, 15, 10, 2, 3, 8, 1, 0, 7, 5, 9],
-    [5, 8, 1, 13, 10, 3, 4, 2, 14, 15, 12, 7, 6, 0, 9, 11],
-    [7, 13, 10, 1, 0, 8, 9, 15, 14, 4, 6, 12, 11, 2, 5, 3],
-    [6, 12, 7, 1, 5, 15, 13, 8, 4, 10, 9, 14, 0, 3, 11, 2],
-    [4, 11, 10, 0, 7, 2, 1, 13, 3, 6, 8, 5, 9, 12, 15, 14],
-    [13, 11, 4, 1, 3, 15, 5, 9, 0, 10, 14, 7, 6, 8, 2, 12],
-    [1, 15, 13, 0, 5, 7, 10, 4, 9, 2, 3, 14, 6, 11, 8, 12],
-];
-
-pub(super) fn substitute(input: u32, tables: [[u8; 16]; 8]) -> u32 {
+use std::{fmt, str::FromStr};
+
+/// A named set of the eight 4-bit S-boxes, table `i` replaces nibble `i`
+#[derive(Clone, Copy)]
+pub struct SBoxSet {
+    pub name: &'static str,
+    pub tables: [[u8; 16]; 8],
+    pub(super) round_tables: &'static RoundTables,
+}
+
+/// Substitution and the rotation by 11 bits of the round function merged
+/// into a table per byte of the word, so a round takes four lookups
+pub(super) struct RoundTables {
+    pub(super) standard: [[u32; 256]; 4],
+    pub(super) legacy: [[u32; 256]; 4],
+}
+
+impl RoundTables {
+    const fn new(tables: &[[u8; 16]; 8]) -> Self {
+        let mut standard = [[0; 256]; 4];
+        let mut legacy = [[0; 256]; 4];
+
+        let mut i = 0;
+        while i < 4 {
+            let mut byte = 0;
+            while byte < 256 {
+                // Zero bytes get substituted too, only byte `i` is kept
+                let input = (byte as u32) << (i * 8);
+                let mask = 0xff << (i * 8);
+                standard[i][byte] = (substitute(input, tables) & mask).rotate_left(11);
+                legacy[i][byte] = (substitute_legacy(input, tables) & mask).rotate_left(11);
+                byte += 1;
+            }
+            i += 1;
+        }
+
+        Self { standard, legacy }
+    }
+}
+
+/// Builds a set together with its round tables
+macro_rules! sbox_set {
+    (name: $name:expr, tables: $tables:expr $(,)?) => {
+        SBoxSet {
+            name: $name,
+            tables: $tables,
+            round_tables: &RoundTables::new(&$tables),
+        }
+    };
+}
+
+impl SBoxSet {
+    pub const GOST_28147_TEST: Self = sbox_set! {
+        name: "id-Gost28147-89-TestParamSet",
+        tables: [
+            [4, 2, 15, 5, 9, 1, 0, 8, 14, 3, 11, 12, 13, 7, 10, 6],
+            [12, 9, 15, 14, 8, 1, 3, 10, 2, 7, 4, 13, 6, 0, 11, 5],
+            [13, 8, 14, 12, 7, 3, 9, 10, 1, 5, 2, 4, 6, 15, 0, 11],
+            [14, 9, 11, 2, 5, 15, 7, 1, 0, 13, 12, 6, 10, 4, 3, 8],
+            [3, 14, 5, 9, 6, 8, 0, 13, 10, 11, 7, 12, 2, 1, 15, 4],
+            [8, 15, 6, 11, 1, 9, 12, 5, 13, 3, 7, 10, 0, 14, 2, 4],
+            [9, 11, 12, 0, 3, 6, 7, 5, 4, 8, 14, 15, 1, 10, 2, 13],
+            [12, 6, 5, 2, 11, 0, 9, 13, 3, 14, 7, 10, 15, 4, 1, 8],
+        ],
+    };
+
+    pub const CRYPTO_PRO_A: Self = sbox_set! {
+        name: "id-Gost28147-89-CryptoPro-A-ParamSet",
+        tables: [
+            [9, 6, 3, 2, 8, 11, 1, 7, 10, 4, 14, 15, 12, 0, 13, 5],
+            [3, 7, 14, 9, 8, 10, 15, 0, 5, 2, 6, 12, 11, 4, 13, 1],
+            [14, 4, 6, 2, 11, 3, 13, 8, 12, 15, 5, 10, 0, 7, 1, 9],
+            [14, 7, 10, 12, 13, 1, 3, 9, 0, 2, 11, 4, 15, 8, 5, 6],
+            [11, 5, 1, 9, 8, 13, 15, 0, 14, 4, 2, 3, 12, 7, 10, 6],
+            [3, 10, 13, 12, 1, 2, 0, 11, 7, 5, 9, 4, 8, 15, 14, 6],
+            [1, 13, 2, 9, 7, 10, 6, 0, 8, 12, 4, 5, 15, 3, 11, 14],
+            [11, 10, 15, 5, 0, 12, 14, 8, 6, 2, 3, 9, 1, 7, 13, 4],
+        ],
+    };
+
+    pub const CRYPTO_PRO_B: Self = sbox_set! {
+        name: "id-Gost28147-89-CryptoPro-B-ParamSet",
+        tables: [
+            [8, 4, 11, 1, 3, 5, 0, 9, 2, 14, 10, 12, 13, 6, 7, 15],
+            [0, 1, 2, 10, 4, 13, 5, 12, 9, 7, 3, 15, 11, 8, 6, 14],
+            [14, 12, 0, 10, 9, 2, 13, 11, 7, 5, 8, 15, 3, 6, 1, 4],
+            [7, 5, 0, 13, 11, 6, 1, 2, 3, 10, 12, 15, 4, 14, 9, 8],
+            [2, 7, 12, 15, 9, 5, 10, 11, 1, 4, 0, 13, 6, 8, 14, 3],
+            [8, 3, 2, 6, 4, 13, 14, 11, 12, 1, 7, 15, 10, 0, 9, 5],
+            [5, 2, 10, 11, 9, 1, 12, 3, 7, 4, 13, 0, 6, 15, 8, 14],
+            [0, 4, 11, 14, 8, 3, 7, 1, 10, 2, 9, 6, 15, 13, 5, 12],
+        ],
+    };
+
+    pub const CRYPTO_PRO_C: Self = sbox_set! {
+        name: "id-Gost28147-89-CryptoPro-C-ParamSet",
+        tables: [
+            [1, 11, 12, 2, 9, 13, 0, 15, 4, 5, 8, 14, 10, 7, 6, 3],
+            [0, 1, 7, 13, 11, 4, 5, 2, 8, 14, 15, 12, 9, 10, 6, 3],
+            [8, 2, 5, 0, 4, 9, 15, 10, 3, 7, 12, 13, 6, 14, 1, 11],
+            [3, 6, 0, 1, 5, 13, 10, 8, 11, 2, 9, 7, 14, 15, 12, 4],
+            [8, 13, 11, 0, 4, 5, 1, 2, 9, 3, 12, 14, 6, 15, 10, 7],
+            [12, 9, 11, 1, 8, 14, 2, 4, 7, 3, 6, 5, 10, 0, 15, 13],
+            [10, 9, 6, 8, 13, 14, 2, 0, 15, 3, 5, 11, 4, 1, 12, 7],
+            [7, 4, 0, 5, 10, 2, 15, 14, 12, 6, 1, 11, 13, 9, 3, 8],
+        ],
+    };
+
+    pub const CRYPTO_PRO_D: Self = sbox_set! {
+        name: "id-Gost28147-89-CryptoPro-D-ParamSet",
+        tables: [
+            [15, 12, 2, 10, 6, 4, 5, 0, 7, 9, 14, 13, 1, 11, 8, 3],
+            [11, 6, 3, 4, 12, 15, 14, 2, 7, 13, 8, 0, 5, 10, 9, 1],
+            [1, 12, 11, 0, 15, 14, 6, 5, 10, 13, 4, 8, 9, 3, 7, 2],
+            [1, 5, 14, 12, 10, 7, 0, 13, 6, 2, 11, 4, 9, 3, 15, 8],
+            [0, 12, 8, 9, 13, 2, 10, 11, 7, 3, 6, 5, 4, 14, 15, 1],
+            [8, 0, 15, 3, 2, 5, 14, 11, 1, 10, 4, 7, 12, 9, 13, 6],
+            [3, 0, 6, 15, 1, 14, 9, 2, 13, 8, 12, 4, 11, 10, 5, 7],
+            [1, 10, 6, 8, 15, 11, 0, 4, 12, 3, 5, 9, 7, 13, 2, 14],
+        ],
+    };
+
+    pub const TC26_Z: Self = sbox_set! {
+        name: "id-tc26-gost-28147-param-Z",
+        tables: [
+            [12, 4, 6, 2, 10, 5, 11, 9, 14, 8, 13, 7, 0, 3, 15, 1],
+            [6, 8, 2, 3, 9, 10, 5, 12, 1, 14, 4, 7, 11, 13, 0, 15],
+            [11, 3, 5, 8, 2, 15, 10, 13, 14, 1, 7, 4, 12, 9, 6, 0],
+            [12, 8, 2, 1, 13, 4, 15, 6, 7, 0, 10, 5, 3, 14, 9, 11],
+            [7, 15, 5, 10, 8, 1, 6, 13, 0, 9, 3, 14, 11, 4, 2, 12],
+            [5, 13, 15, 6, 9, 2, 12, 10, 11, 7, 8, 1, 4, 3, 14, 0],
+            [8, 14, 2, 5, 6, 9, 1, 12, 15, 4, 11, 0, 13, 10, 3, 7],
+            [1, 7, 14, 13, 0, 5, 8, 3, 4, 15, 10, 6, 9, 12, 11, 2],
+        ],
+    };
+
+    pub const GOST_3411_TEST: Self = sbox_set! {
+        name: "id-GostR3411-94-TestParamSet",
+        tables: [
+            [4, 10, 9, 2, 13, 8, 0, 14, 6, 11, 1, 12, 7, 15, 5, 3],
+            [14, 11, 4, 12, 6, 13, 15, 10, 2, 3, 8, 1, 0, 7, 5, 9],
+            [5, 8, 1, 13, 10, 3, 4, 2, 14, 15, 12, 7, 6, 0, 9, 11],
+            [7, 13, 10, 1, 0, 8, 9, 15, 14, 4, 6, 12, 11, 2, 5, 3],
+            [6, 12, 7, 1, 5, 15, 13, 8, 4, 10, 9, 14, 0, 3, 11, 2],
+            [4, 11, 10, 0, 7, 2, 1, 13, 3, 6, 8, 5, 9, 12, 15, 14],
+            [13, 11, 4, 1, 3, 15, 5, 9, 0, 10, 14, 7, 6, 8, 2, 12],
+            [1, 15, 13, 0, 5, 7, 10, 4, 9, 2, 3, 14, 6, 11, 8, 12],
+        ],
+    };
+
+    pub const GOST_3411_CRYPTO_PRO: Self = sbox_set! {
+        name: "id-GostR3411-94-CryptoProParamSet",
+        tables: [
+            [10, 4, 5, 6, 8, 1, 3, 7, 13, 12, 14, 0, 9, 2, 11, 15],
+            [5, 15, 4, 0, 2, 13, 11, 9, 1, 7, 6, 3, 12, 14, 10, 8],
+            [7, 15, 12, 14, 9, 4, 1, 0, 3, 11, 5, 2, 6, 10, 8, 13],
+            [4, 10, 7, 12, 0, 15, 2, 8, 14, 1, 6, 5, 13, 11, 9, 3],
+            [7, 6, 4, 11, 9, 12, 2, 10, 1, 8, 0, 14, 15, 13, 3, 5],
+            [7, 6, 2, 4, 13, 9, 15, 0, 10, 1, 5, 11, 8, 14, 12, 3],
+            [13, 14, 4, 1, 7, 0, 5, 10, 3, 12, 8, 15, 6, 2, 9, 11],
+            [1, 3, 10, 9, 5, 11, 4, 15, 8, 6, 7, 14, 13, 0, 2, 12],
+        ],
+    };
+
+    pub const ALL: [Self; 8] = [
+        Self::GOST_28147_TEST,
+        Self::CRYPTO_PRO_A,
+        Self::CRYPTO_PRO_B,
+        Self::CRYPTO_PRO_C,
+        Self::CRYPTO_PRO_D,
+        Self::TC26_Z,
+        Self::GOST_3411_TEST,
+        Self::GOST_3411_CRYPTO_PRO,
+    ];
+
+    /// Finds a set by its name, ignoring case
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|set| set.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// The set `lab1` has always used, also the default one for GOST R 34.11-94
+impl Default for SBoxSet {
+    fn default() -> Self {
+        Self::GOST_3411_TEST
+    }
+}
+
+impl fmt::Debug for SBoxSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SBoxSet")
+            .field("name", &self.name)
+            .field("tables", &self.tables)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The round tables follow from the S-boxes
+impl PartialEq for SBoxSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.tables == other.tables
+    }
+}
+
+impl Eq for SBoxSet {}
+
+impl fmt::Display for SBoxSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl FromStr for SBoxSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::by_name(s).ok_or_else(|| {
+            let names: Vec<_> = Self::ALL.iter().map(|set| set.name).collect();
+            format!("unknown S-box set, expected one of: {}", names.join(", "))
+        })
+    }
+}
+
+/// Replaces nibble `i` of the word, counting from the least significant one,
+/// using table `i` as GOST 28147-89 specifies
+pub(super) const fn substitute(input: u32, tables: &[[u8; 16]; 8]) -> u32 {
+    let mut bytes = input.to_le_bytes();
+    let mut i = 0;
+    while i < 4 {
+        let first_half = (bytes[i] >> 4) & 0b1111;
+        let last_half = bytes[i] & 0b1111;
+
+        bytes[i] =
+            (tables[i * 2 + 1][first_half as usize] << 4) | tables[i * 2][last_half as usize];
+        i += 1;
+    }
+    u32::from_le_bytes(bytes)
+}
+
+/// Substitution of the first versions of `lab1`, which only used
+/// tables 0, 1, 2, 3 for high nibbles and 0, 2, 4, 6 for low ones
+pub(super) const fn substitute_legacy(input: u32, tables: &[[u8; 16]; 8]) -> u32 {
     let mut bytes = input.to_le_bytes();
-    for (i, byte) in bytes.iter_mut().enumerate() {
-        let first_half = (*byte >> 4) & 0b1111;
-        let last_half = *byte & 0b1111;
+    let mut i = 0;
+    while i < 4 {
+        let first_half = (bytes[i] >> 4) & 0b1111;
+        let last_half = bytes[i] & 0b1111;
 
-        *byte = (tables[i][first_half as usize] << 4) | tables[i * 2][last_half as usize]
+        bytes[i] = (tables[i][first_half as usize] << 4) | tables[i * 2][last_half as usize];
+        i += 1;
     }
     u32::from_le_bytes(bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_permutations() {
+        for set in SBoxSet::ALL {
+            for table in set.tables {
+                let mut sorted = table;
+                sorted.sort();
+                assert_eq!(sorted, std::array::from_fn(|i| i as u8), "{set}");
+            }
+        }
+    }
+
+    #[test]
+    fn by_name() {
+        for set in SBoxSet::ALL {
+            assert_eq!(set.name.parse(), Ok(set));
+        }
+        assert_eq!(
+            SBoxSet::by_name("ID-TC26-GOST-28147-PARAM-Z"),
+            Some(SBoxSet::TC26_Z)
+        );
+        assert!("unknown".parse::<SBoxSet>().is_err());
+    }
+}
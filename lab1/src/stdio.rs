@@ -0,0 +1,116 @@
+//! Files named on the command line of lab1 and lab2, `-` stands for stdin and stdout
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Cursor, Read, Seek, SeekFrom, Stdin, Write},
+    path::Path,
+};
+
+/// Buffer size for inputs of unknown length
+pub const STREAM_BUFF_SIZE: usize = 1 << 20;
+
+fn is_std(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// A file to read or stdin
+#[derive(Debug)]
+pub enum Input {
+    File(File),
+    Stdin(Stdin),
+    /// The rest of stdin read to the end, for what goes over the input twice
+    Memory(Cursor<Vec<u8>>),
+}
+
+impl Input {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if is_std(path) {
+            return Ok(Self::Stdin(io::stdin()));
+        }
+
+        File::open(path).map(Self::File)
+    }
+
+    /// Length of a regular file or of what was read to memory,
+    /// pipes and terminals don't know theirs
+    pub fn known_len(&self) -> Option<u64> {
+        match self {
+            Self::File(file) => file
+                .metadata()
+                .ok()
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len()),
+            Self::Stdin(_) => None,
+            Self::Memory(data) => Some(data.get_ref().len() as u64),
+        }
+    }
+
+    /// Reads the rest of stdin to memory, so the input can seek
+    pub fn seekable(self) -> io::Result<Self> {
+        match self {
+            Self::Stdin(stdin) => {
+                let mut data = Vec::new();
+                stdin.lock().read_to_end(&mut data)?;
+                Ok(Self::Memory(Cursor::new(data)))
+            }
+            input => Ok(input),
+        }
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.read(buf),
+            Self::Stdin(stdin) => stdin.read(buf),
+            Self::Memory(data) => data.read(buf),
+        }
+    }
+}
+
+/// Stdin can't seek, `Input::seekable` makes an input that can
+impl Seek for Input {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(file) => file.seek(pos),
+            Self::Stdin(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "stdin can't seek",
+            )),
+            Self::Memory(data) => data.seek(pos),
+        }
+    }
+}
+
+/// Buffer size for an input of `len` bytes, at most `max`
+pub fn buff_size(len: Option<u64>, max: u64) -> usize {
+    len.map_or(STREAM_BUFF_SIZE, |len| len.min(max) as usize)
+}
+
+/// Creates the file or takes stdout, writes are buffered and must be flushed
+pub fn create_output(path: &Path) -> io::Result<Box<dyn Write>> {
+    if is_std(path) {
+        return Ok(Box::new(BufWriter::new(io::stdout().lock())));
+    }
+
+    Ok(Box::new(BufWriter::new(File::create(path)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_seeks() {
+        let mut input = Input::Memory(Cursor::new(b"header data".to_vec()));
+        assert_eq!(input.known_len(), Some(11));
+
+        input.seek(SeekFrom::Start(7)).unwrap();
+        let mut data = String::new();
+        input.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "data");
+
+        assert_eq!(buff_size(input.known_len(), 4), 4);
+        assert_eq!(buff_size(None, 4), STREAM_BUFF_SIZE);
+    }
+}
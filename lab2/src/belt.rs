@@ -0,0 +1,65 @@
+use lab1::{BlockCipher, ByteOrder};
+use zeroize::Zeroize;
+
+use crate::{crypt::crypt, decrypt::decrypt};
+
+/// The BelT block cipher, blocks are little-endian numbers
+#[derive(Debug, Clone)]
+pub struct Belt {
+    key: [u32; 8],
+}
+
+impl Drop for Belt {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl BlockCipher for Belt {
+    type Block = u128;
+    type Key = [u32; 8];
+
+    fn new(key: &[u32; 8]) -> Self {
+        Self { key: *key }
+    }
+
+    fn encrypt(&self, block: u128) -> u128 {
+        crypt(block, self.key)
+    }
+
+    fn decrypt(&self, block: u128) -> u128 {
+        decrypt(block, self.key)
+    }
+
+    fn byte_order(&self) -> ByteOrder {
+        ByteOrder::Little
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lab1::{cipher_feedback, cipher_feedback_decrypt};
+
+    #[test]
+    fn gamma() {
+        let init = 0xBADF00D;
+        let cipher = Belt::new(&[2341, 325, 532, 12, 5325, 234, 52, 2]);
+        let message = [2342, 352, 6, 6436];
+
+        let mut copy = message;
+
+        cipher_feedback(&cipher, init, &mut copy);
+        assert_ne!(message, copy);
+        cipher_feedback_decrypt(&cipher, init, &mut copy);
+        assert_eq!(message, copy);
+    }
+
+    #[test]
+    fn decrypt_reverses_encrypt() {
+        let cipher = Belt::new(&[2341, 325, 532, 12, 5325, 234, 52, 2]);
+        let block = 0x0123456789abcdeffedcba9876543210;
+
+        assert_eq!(cipher.decrypt(cipher.encrypt(block)), block);
+    }
+}
@@ -5,12 +5,19 @@ use crate::transform::wrapping::*;
 use crate::misc::{u128_to_wrapping_u32, wrapping_u32_to_u128};
 
 pub fn crypt(word: u128, key: [u32; 8]) -> u128 {
+    crypt_rounds(word, key, 8)
+}
+
+/// Encryption reduced to the first `rounds` of the 8 rounds
+pub fn crypt_rounds(word: u128, key: [u32; 8], rounds: usize) -> u128 {
+    assert!((1..=8).contains(&rounds), "BelT has 1 to 8 rounds");
+
     let [mut a, mut b, mut c, mut d] = u128_to_wrapping_u32(word);
     let mut e;
 
     let t_key = |index: usize| Wrapping(key[index % 8]);
 
-    for i in 1..=8usize {
+    for i in 1..=rounds {
         let i7 = i * 7;
 
         b ^= g5(a + t_key(i7 - 6));
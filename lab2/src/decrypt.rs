@@ -4,14 +4,13 @@ use crate::transform::wrapping::*;
 
 use crate::misc::{u128_to_wrapping_u32, wrapping_u32_to_u128};
 
-#[allow(unused)]
 pub fn decrypt(word: u128, key: [u32; 8]) -> u128 {
     let [mut a, mut b, mut c, mut d] = u128_to_wrapping_u32(word);
     let mut e;
 
     let t_key = |index: usize| Wrapping(key[index % 8]);
 
-    for i in 1..=8usize {
+    for i in (1..=8usize).rev() {
         let i7 = i * 7;
 
         b ^= g5(a + t_key(i7));
@@ -29,7 +28,7 @@ pub fn decrypt(word: u128, key: [u32; 8]) -> u128 {
 
         swap(&mut a, &mut b);
         swap(&mut c, &mut d);
-        swap(&mut b, &mut c);
+        swap(&mut a, &mut d);
     }
 
     wrapping_u32_to_u128([c, a, d, b])
@@ -0,0 +1,269 @@
+//! Differential trails of BelT reduced to a few rounds. The G functions take
+//! the bytes of a word through the S-box and rotate it, additions and
+//! subtractions modulo 2^32 keep the XOR of the differences.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use lab1::{
+    addition_weight, beam_search, substitution_outputs, Trail, Transitions, SUBSTITUTION_BRANCHES,
+};
+
+use crate::subs::s_box;
+
+/// Differences of the words a, b, c, d and e of a round
+type State = [u32; 5];
+
+/// Output differences of the bytes of the G functions going through the S-box,
+/// kept for the input differences seen so far
+struct Substitution {
+    transitions: Transitions,
+    outputs: RefCell<HashMap<u32, Vec<(u32, f64)>>>,
+}
+
+impl Substitution {
+    fn new() -> Self {
+        Self {
+            transitions: Transitions::new(s_box()).unwrap(),
+            outputs: RefCell::default(),
+        }
+    }
+
+    /// Differences of the output of G_r for the input difference `x`
+    fn g_outputs(&self, x: u32, r: u32) -> Vec<(u32, f64)> {
+        let mut outputs = self.outputs.borrow_mut();
+
+        let outputs = outputs.entry(x).or_insert_with(|| {
+            let transitions = [&self.transitions; 4];
+
+            substitution_outputs(&x.to_le_bytes(), &transitions, SUBSTITUTION_BRANCHES)
+                .into_iter()
+                .map(|(bytes, weight)| (u32::from_le_bytes(bytes.try_into().unwrap()), weight))
+                .collect()
+        });
+
+        outputs
+            .iter()
+            .map(|&(output, weight)| (output.rotate_left(r), weight))
+            .collect()
+    }
+}
+
+/// A step of the round: `input` gives the difference going into G_r and the weight
+/// of getting it, `apply` puts the output difference into the state and returns
+/// the weight of doing so. Only the lightest states are kept.
+fn step(
+    states: Vec<(State, f64)>,
+    substitution: &Substitution,
+    r: u32,
+    input: impl Fn(&State) -> (u32, f64),
+    apply: impl Fn(&mut State, u32) -> f64,
+) -> Vec<(State, f64)> {
+    let apply = &apply;
+
+    let mut next: Vec<_> = states
+        .iter()
+        .flat_map(|&(state, weight)| {
+            let (x, into) = input(&state);
+
+            substitution
+                .g_outputs(x, r)
+                .into_iter()
+                .map(move |(g, through)| {
+                    let mut state = state;
+                    let weight = weight + into + through + apply(&mut state, g);
+                    (state, weight)
+                })
+        })
+        .collect();
+
+    next.sort_by(|x, y| x.1.total_cmp(&y.1));
+    next.truncate(SUBSTITUTION_BRANCHES);
+    next
+}
+
+/// Differences after a round for the differences a, b, c, d before it.
+/// Adding a key word or the round number keeps the difference with the
+/// weight of adding a zero one.
+fn round_outputs(words: [u32; 4], substitution: &Substitution) -> Vec<([u32; 4], f64)> {
+    let [a, b, c, d] = words;
+    let mut states = vec![([a, b, c, d, 0], 0.0)];
+
+    // b ^= G5(a + k)
+    states = step(
+        states,
+        substitution,
+        5,
+        |s| (s[0], addition_weight(s[0], 0)),
+        |s, g| {
+            s[1] ^= g;
+            0.0
+        },
+    );
+    // c ^= G21(d + k)
+    states = step(
+        states,
+        substitution,
+        21,
+        |s| (s[3], addition_weight(s[3], 0)),
+        |s, g| {
+            s[2] ^= g;
+            0.0
+        },
+    );
+    // a -= G13(b + k)
+    states = step(
+        states,
+        substitution,
+        13,
+        |s| (s[1], addition_weight(s[1], 0)),
+        |s, g| {
+            let weight = addition_weight(s[0], g);
+            s[0] ^= g;
+            weight
+        },
+    );
+    // e = G21(b + c + k) + i, b += e, c -= e
+    states = step(
+        states,
+        substitution,
+        21,
+        |s| {
+            let sum = s[1] ^ s[2];
+            (sum, addition_weight(s[1], s[2]) + addition_weight(sum, 0))
+        },
+        |s, g| {
+            let weight =
+                addition_weight(g, 0) + addition_weight(s[1], g) + addition_weight(s[2], g);
+            s[4] = g;
+            s[1] ^= g;
+            s[2] ^= g;
+            weight
+        },
+    );
+    // d += G13(c + k)
+    states = step(
+        states,
+        substitution,
+        13,
+        |s| (s[2], addition_weight(s[2], 0)),
+        |s, g| {
+            let weight = addition_weight(s[3], g);
+            s[3] ^= g;
+            weight
+        },
+    );
+    // b ^= G21(a + k)
+    states = step(
+        states,
+        substitution,
+        21,
+        |s| (s[0], addition_weight(s[0], 0)),
+        |s, g| {
+            s[1] ^= g;
+            0.0
+        },
+    );
+    // c ^= G5(d + k)
+    states = step(
+        states,
+        substitution,
+        5,
+        |s| (s[3], addition_weight(s[3], 0)),
+        |s, g| {
+            s[2] ^= g;
+            0.0
+        },
+    );
+
+    // The swaps at the end of the round
+    states
+        .into_iter()
+        .map(|([a, b, c, d, _], weight)| ([b, d, a, c], weight))
+        .collect()
+}
+
+/// The block of the words `words`, a is the lowest one
+fn block(words: [u32; 4]) -> u128 {
+    words
+        .iter()
+        .rev()
+        .fold(0, |block, &word| block << 32 | word as u128)
+}
+
+/// Searches for light trails over `rounds` rounds of `crypt_rounds`, starting
+/// from every difference in a single byte. Returns up to `width` trails,
+/// the lightest first.
+pub fn belt_trails(rounds: usize, width: usize) -> Vec<Trail<u128>> {
+    let substitution = Substitution::new();
+
+    let starts = (0..16).flat_map(|n| {
+        (1..256).map(move |d| {
+            let mut words = [0; 4];
+            words[n / 4] = d << (n % 4 * 8);
+            words
+        })
+    });
+
+    // The output of the cipher is the state taken as b, d, a, c
+    beam_search(starts, rounds, width, |words| {
+        round_outputs(words, &substitution)
+    })
+    .into_iter()
+    .map(|(path, weight)| Trail {
+        differences: path
+            .iter()
+            .enumerate()
+            .map(|(i, &[a, b, c, d])| match i {
+                0 => block([a, b, c, d]),
+                _ => block([b, d, a, c]),
+            })
+            .collect(),
+        weight,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::Wrapping;
+
+    use super::*;
+    use crate::transform::wrapping::g13;
+
+    #[test]
+    fn g_outputs_match_g() {
+        let substitution = Substitution::new();
+        let input = 0x0000_5a00;
+
+        // Only the byte with the difference matters
+        for (output, weight) in substitution.g_outputs(input, 13) {
+            let right = (0..256)
+                .map(|byte| 0x1234_0078 | byte << 8)
+                .filter(|&x| (g13(Wrapping(x)) ^ g13(Wrapping(x ^ input))).0 == output)
+                .count();
+
+            assert_eq!(right as f64, 256.0 * (-weight).exp2());
+        }
+    }
+
+    #[test]
+    fn trails_start_from_a_byte() {
+        let trails = belt_trails(2, 4);
+
+        assert_eq!(trails.len(), 4);
+        for trail in &trails {
+            assert_eq!(trail.rounds(), 2);
+            assert_eq!(
+                trail
+                    .input()
+                    .to_le_bytes()
+                    .iter()
+                    .filter(|&&b| b != 0)
+                    .count(),
+                1
+            );
+            assert!(trail.weight >= 5.0);
+        }
+        assert!(trails.windows(2).all(|w| w[0].weight <= w[1].weight));
+    }
+}
@@ -1,82 +1,591 @@
+mod belt;
 mod crypt;
 mod decrypt;
-mod gamma;
+mod differential;
 mod misc;
 mod subs;
 mod transform;
 
-use std::{io::*, path::PathBuf};
+use std::{
+    io::*,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
 
+use belt::Belt;
+use block_buffer::BlockBuffer;
 use clap::Parser;
-use gamma::*;
+use lab1::{
+    buff_size, counter_parallel, create_output, key_check_value, BlockCipher, CfbDecryptReader,
+    CfbEncryptWriter, CipherId, CounterGamma, CtrDecryptReader, Header, Input, KdfParams, KeyFile,
+    Mgm, MgmBlock, ModeId, TagMismatch, UNKNOWN_LEN,
+};
+use zeroize::Zeroizing;
 
 const MAX_BUFF_SIZE: u64 = 1 << 30;
 const INIT_BLOCK: u128 = 0xBADF00D;
 const DEFAULT_KEY: [u32; 8] = [34, 42, 12, 53, 23, 23, 54, 34];
 
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Mode {
+    /// Gamming with feedback
+    Cfb,
+    /// Gamming, the counter mode of STB 34.101.31
+    Ctr,
+}
+
 #[derive(Debug, clap::Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Commands {
+    /// Report the DDT, LAT, differential uniformity, nonlinearity,
+    /// algebraic degree and fixed points of the BelT S-box
+    AnalyzeSbox {
+        #[arg(long, help = "Also print the DDT and the LAT")]
+        tables: bool,
+    },
+    /// Search for differential trails of BelT reduced to a few rounds
+    /// and count how often they hold under a random key. Only right pairs
+    /// are counted, the toy key recovery is for GOST 28147-89 only, see `lab1 trail --attack`.
+    Trail {
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u8).range(1..=8),
+            help = "Rounds the trails cover"
+        )]
+        rounds: u8,
+
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 8,
+            value_parser = clap::value_parser!(u16).range(1..),
+            help = "Trails kept after every round of the search"
+        )]
+        width: u16,
+
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 1 << 16,
+            help = "Random pairs a trail is checked with"
+        )]
+        pairs: usize,
+    },
+}
+
+#[derive(Debug, clap::Args)]
 struct Args {
     #[arg(short, long)]
     decrypt: bool,
 
-    #[arg(value_name = "FILE")]
-    input: PathBuf,
+    #[arg(short, long, value_enum, default_value_t = Mode::Cfb)]
+    mode: Mode,
 
-    #[arg(short, long)]
-    output: PathBuf,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+        help = "Threads for ctr mode"
+    )]
+    threads: NonZeroUsize,
+
+    #[arg(
+        long,
+        conflicts_with = "mode",
+        help = "Authenticated encryption in MGM, the tag is appended to the output"
+    )]
+    aead: bool,
+
+    #[arg(
+        long,
+        requires = "aead",
+        value_name = "HEX",
+        help = "MGM nonce of 16 bytes, the highest bit must be zero"
+    )]
+    nonce: Option<String>,
+
+    #[arg(
+        long,
+        requires = "aead",
+        value_name = "FILE",
+        help = "File with associated data to authenticate"
+    )]
+    aad: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Don't write or expect a header, the IV is the fixed one of older versions"
+    )]
+    no_header: bool,
+
+    #[arg(
+        long,
+        value_name = "OFFSET:LEN",
+        value_parser = parse_range,
+        requires = "decrypt",
+        conflicts_with = "aead",
+        help = "Decrypt only LEN bytes of the plaintext from OFFSET, ctr mode only"
+    )]
+    range: Option<ByteRange>,
+
+    #[arg(
+        value_name = "FILE",
+        required = true,
+        help = "File to read, - for stdin"
+    )]
+    input: Option<PathBuf>,
+
+    #[arg(short, long, required = true, help = "File to write, - for stdout")]
+    output: Option<PathBuf>,
 
     #[arg(short, long)]
     key_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "key_file",
+        help = "Use the built-in key everyone knows instead of a passphrase, for tests only"
+    )]
+    insecure_default_key: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = lab5::KDF_ITERATIONS,
+        value_parser = clap::value_parser!(u32).range(1..=lab1::MAX_KDF_ITERATIONS as i64),
+        help = "Iterations of the key derivation from a passphrase"
+    )]
+    kdf_iterations: u32,
+}
+
+/// A part of the plaintext, `len` bytes from `offset`
+#[derive(Debug, Clone, Copy)]
+struct ByteRange {
+    offset: u64,
+    len: u64,
+}
+
+fn parse_range(s: &str) -> std::result::Result<ByteRange, String> {
+    let (offset, len) = s.split_once(':').ok_or("expected OFFSET:LEN")?;
+
+    Ok(ByteRange {
+        offset: offset.parse().map_err(|e| format!("offset: {e}"))?,
+        len: len.parse().map_err(|e| format!("length: {e}"))?,
+    })
+}
+
+/// Reads the header when decrypting and takes the mode from it.
+/// When encrypting makes a header with a random IV.
+fn header(args: &mut Args, input: &mut Input, len: Option<u64>) -> Result<Header> {
+    if !args.decrypt {
+        let mode = match args.mode {
+            _ if args.aead => ModeId::Mgm,
+            Mode::Cfb => ModeId::Cfb,
+            Mode::Ctr => ModeId::Ctr,
+        };
+        let len = len.unwrap_or(UNKNOWN_LEN);
+        let mut header = Header::random(CipherId::Belt, mode, len, 16);
+
+        if args.key_file.is_none() && !args.insecure_default_key {
+            header.kdf = Some(KdfParams::random(args.kdf_iterations));
+        }
+
+        if args.aead {
+            match &args.nonce {
+                Some(hex) => header.iv = parse_nonce(hex)?.to_be_bytes().to_vec(),
+                None => header.iv[0] &= 0x7f,
+            }
+        }
+
+        return Ok(header);
+    }
+
+    let header = Header::read_from(input)?;
+
+    if header.cipher != CipherId::Belt || header.iv.len() != 16 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "the file isn't encrypted with BelT",
+        ));
+    }
+
+    args.aead = header.mode == ModeId::Mgm;
+    args.mode = match header.mode {
+        ModeId::Cfb => Mode::Cfb,
+        ModeId::Ctr => Mode::Ctr,
+        ModeId::Mgm => args.mode,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "the file is encrypted in a mode lab2 doesn't support",
+            ))
+        }
+    };
+
+    Ok(header)
+}
+
+/// BelT keys are little-endian words
+fn key_words(bytes: &[u8; 32]) -> [u32; 8] {
+    std::array::from_fn(|i| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()))
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let cli = Cli::parse();
 
-    let mut file = std::fs::File::open(args.input)?;
+    let mut args = match cli.command {
+        Some(Commands::AnalyzeSbox { tables }) => {
+            let report = lab1::SBoxReport::new(subs::s_box())?;
+            println!("belt: {report}");
 
-    let mut out_file = std::fs::File::create(args.output)?;
+            if tables {
+                report.write_tables(&mut stdout().lock())?;
+            }
 
-    let key = if let Some(key_file_path) = args.key_file {
-        let mut key_file = std::fs::File::open(key_file_path)?;
+            return Ok(());
+        }
+        Some(Commands::Trail {
+            rounds,
+            width,
+            pairs,
+        }) => {
+            let key: [u32; 8] = rand::random();
 
-        let mut buff = [0u8; 32];
+            for trail in differential::belt_trails(rounds as usize, width as usize) {
+                let differences: Vec<_> = trail
+                    .differences
+                    .iter()
+                    .map(|difference| format!("{difference:032x}"))
+                    .collect();
 
-        key_file.read_exact(&mut buff)?;
+                let right = lab1::right_pairs(&trail, pairs, &mut rand::thread_rng(), |x| {
+                    crypt::crypt_rounds(x, key, rounds as usize)
+                });
 
-        buff.chunks_exact(4)
-            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
-            .collect::<Vec<u32>>()
-            .try_into()
-            .unwrap()
-    } else {
-        DEFAULT_KEY
+                println!(
+                    "{} weight {:.2}: {right} of {pairs} pairs, {:.2e} expected",
+                    differences.join(" -> "),
+                    trail.weight,
+                    pairs as f64 * (-trail.weight).exp2()
+                );
+            }
+
+            return Ok(());
+        }
+        None => cli.args,
+    };
+
+    // Clap requires both when there is no subcommand
+    let (Some(input), Some(output)) = (args.input.clone(), args.output.clone()) else {
+        unreachable!()
     };
 
-    let transform_func = if args.decrypt {
-        gamma_decrypt
+    let mut file = Input::open(&input)?;
+
+    let len = file.known_len();
+
+    let mut header = if args.no_header {
+        None
     } else {
-        gamma_crypt
+        Some(header(&mut args, &mut file, len)?)
     };
 
-    let len = file.metadata()?.len();
+    let kdf = header.as_mut().and_then(|header| header.kdf.as_mut());
 
-    let buff_size = len.min(MAX_BUFF_SIZE) as usize;
+    let buff = match (&args.key_file, kdf) {
+        (Some(key_file_path), _) => {
+            let key_file = KeyFile::read(key_file_path)?;
+            key_file.check("belt", &Belt::new(&key_words(&key_file.key)))?;
+            Zeroizing::new(key_file.key)
+        }
+        (None, Some(kdf)) => {
+            let passphrase = lab5::read_passphrase(!args.decrypt)?;
+            let key = lab5::derive_key(passphrase.as_bytes(), &kdf.salt, kdf.iterations);
+            let check_value = key_check_value(&Belt::new(&key_words(&key)));
 
-    let mut buff = block_buffer::BlockBuffer::new(buff_size);
+            if args.decrypt {
+                kdf.verify(check_value)?;
+            } else {
+                kdf.check_value = check_value;
+            }
 
-    let mut prev = INIT_BLOCK;
+            key
+        }
+        (None, None) if args.insecure_default_key => Zeroizing::new(std::array::from_fn(|i| {
+            DEFAULT_KEY[i / 4].to_le_bytes()[i % 4]
+        })),
+        (None, None) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "no key: there is no header with a passphrase salt, \
+                 give --key-file or --insecure-default-key",
+            ))
+        }
+    };
+
+    let key = Zeroizing::new(key_words(&buff));
+
+    if args.aead {
+        return aead(&args, file, &output, header.as_ref(), &key, len);
+    }
+
+    // BelT takes blocks as little-endian numbers
+    let iv = match &header {
+        Some(header) => u128::from_le_bytes(header.iv.as_slice().try_into().unwrap()),
+        None => INIT_BLOCK,
+    };
+
+    let cipher = Belt::new(&key);
+
+    if let Some(range) = args.range {
+        return decrypt_range(&args, cipher, iv, file, &output, header.as_ref(), range);
+    }
+
+    let mut out_file = create_output(&output)?;
+
+    if let (Some(header), false) = (&header, args.decrypt) {
+        header.write_to(&mut out_file)?;
+    }
+
+    let written = match (args.mode, args.decrypt) {
+        (Mode::Ctr, _) => counter_mode(&args, &cipher, iv, &mut file, out_file, len)?,
+        (Mode::Cfb, true) => {
+            let mut reader = CfbDecryptReader::new(cipher, iv, file);
+            let written = copy(&mut reader, &mut out_file)?;
+            out_file.flush()?;
+            written
+        }
+        (Mode::Cfb, false) => {
+            let mut writer = CfbEncryptWriter::new(cipher, iv, out_file);
+            let written = copy(&mut file, &mut writer)?;
+            writer.finish()?;
+            written
+        }
+    };
+
+    // A stream of unknown length ends with its ciphertext
+    let known_len = header.as_ref().and_then(Header::known_len);
+    if let (Some(len), true) = (known_len, args.decrypt) {
+        if len != written {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "the ciphertext length doesn't match its header",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Counter mode: the counter starts from the encrypted IV and is incremented
+/// before every block, so encryption and decryption are the same.
+/// Returns the number of bytes written.
+fn counter_mode(
+    args: &Args,
+    cipher: &Belt,
+    iv: u128,
+    input: &mut Input,
+    mut output: impl Write,
+    len: Option<u64>,
+) -> Result<u64> {
+    let mut buff = BlockBuffer::<u128>::new(buff_size(len, MAX_BUFF_SIZE));
+    let mut counter = cipher.encrypt(iv).wrapping_add(1);
+    let mut written = 0;
 
     loop {
-        if buff.read_bytes_from(&mut file)? == 0 {
+        let read = buff.read_bytes_from(input)?;
+
+        if read == 0 {
             break;
         }
 
-        prev = transform_func(prev, &mut buff, key);
+        counter = counter_parallel(cipher, counter, &mut buff, args.threads.get());
+        output.write_all(&buff.as_bytes()[..read])?;
+        written += read as u64;
+    }
+
+    output.flush()?;
+    Ok(written)
+}
+
+/// Decrypts a part of a ctr mode ciphertext, the gamma starts right at it
+fn decrypt_range(
+    args: &Args,
+    cipher: Belt,
+    iv: u128,
+    input: Input,
+    output: &Path,
+    header: Option<&Header>,
+    range: ByteRange,
+) -> Result<()> {
+    if !matches!(args.mode, Mode::Ctr) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "a range can be decrypted in ctr mode only",
+        ));
+    }
 
-        out_file.write_all(buff.as_ref())?;
+    let end = range.offset.checked_add(range.len);
+    let known_len = header.and_then(Header::known_len);
+    if known_len.is_some_and(|len| end.is_none_or(|end| end > len)) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the range is past the end of the plaintext",
+        ));
     }
 
-    out_file.set_len(len)?;
+    // The counter of the first block, as in `counter_mode`
+    let counter = cipher.encrypt(iv).wrapping_add(1);
+    let input = input.seekable()?;
+    let mut reader = CtrDecryptReader::new(CounterGamma::new(cipher, counter), input)?;
+    reader.seek(SeekFrom::Start(range.offset))?;
 
-    Ok(())
+    let mut output = create_output(output)?;
+    copy(&mut reader.take(range.len), &mut output)?;
+    output.flush()
+}
+
+fn parse_nonce(hex: &str) -> Result<u128> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, "invalid nonce");
+
+    if hex.len() != 32 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let nonce = u128::from_str_radix(hex, 16).map_err(|_| invalid())?;
+
+    if nonce >> 127 != 0 {
+        return Err(invalid());
+    }
+
+    Ok(nonce)
+}
+
+fn aead(
+    args: &Args,
+    mut input: Input,
+    output: &Path,
+    header: Option<&Header>,
+    key: &[u32; 8],
+    len: Option<u64>,
+) -> Result<()> {
+    let associated = match &args.aad {
+        Some(path) => std::fs::read(path)?,
+        None => Vec::new(),
+    };
+
+    let nonce = match (header, &args.nonce) {
+        (Some(header), _) => u128::from_be_bytes(header.iv.as_slice().try_into().unwrap()),
+        (None, Some(hex)) => parse_nonce(hex)?,
+        // A repeated MGM nonce gives away the plaintext and the tags
+        (None, None) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "--aead without a header needs a --nonce",
+            ))
+        }
+    };
+
+    let cipher = Belt::new(key);
+
+    // MGM takes blocks as big-endian numbers, BelT as little-endian ones
+    let encrypt = |block: u128| {
+        let block = u128::from_le_bytes(block.to_be_bytes());
+        u128::from_be_bytes(cipher.encrypt(block).to_le_bytes())
+    };
+
+    let mut buff = BlockBuffer::<u128>::new(buff_size(len, MAX_BUFF_SIZE));
+
+    let mut mgm = Mgm::new(encrypt, nonce);
+    mgm.associated_data(&associated);
+
+    if !args.decrypt {
+        let mut out_file = create_output(output)?;
+
+        if let Some(header) = header {
+            header.write_to(&mut out_file)?;
+        }
+
+        loop {
+            let read = buff.read_bytes_from(&mut input)?;
+
+            if read == 0 {
+                break;
+            }
+
+            let data = &mut buff.as_bytes_mut()[..read];
+            mgm.encrypt(data);
+            out_file.write_all(data)?;
+        }
+
+        out_file.write_all(&mgm.finish(u128::SIZE))?;
+        return out_file.flush();
+    }
+
+    // The tag is checked before the ciphertext is decrypted, a stream is read to memory for that
+    let mut input = input.seekable()?;
+    let start = input.stream_position()?;
+    let end = input.seek(SeekFrom::End(0))?;
+    input.seek(SeekFrom::Start(start))?;
+
+    let data_len = (end - start)
+        .checked_sub(u128::SIZE as u64)
+        .ok_or(TagMismatch)?;
+
+    if header
+        .and_then(Header::known_len)
+        .is_some_and(|len| len != data_len)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "the ciphertext length doesn't match its header",
+        ));
+    }
+
+    // The whole ciphertext is authenticated before any plaintext is written
+    let mut ciphertext = (&mut input).take(data_len);
+
+    loop {
+        let read = buff.read_bytes_from(&mut ciphertext)?;
+
+        if read == 0 {
+            break;
+        }
+
+        mgm.authenticate(&buff.as_bytes()[..read]);
+    }
+
+    let mut tag = [0; u128::SIZE];
+    input.read_exact(&mut tag)?;
+    mgm.verify(&tag)?;
+
+    input.seek(SeekFrom::Start(start))?;
+    let mut ciphertext = input.take(data_len);
+
+    let mut out_file = create_output(output)?;
+    let mut mgm = Mgm::new(encrypt, nonce);
+
+    loop {
+        let read = buff.read_bytes_from(&mut ciphertext)?;
+
+        if read == 0 {
+            break;
+        }
+
+        let data = &mut buff.as_bytes_mut()[..read];
+        mgm.keystream(data);
+        out_file.write_all(data)?;
+    }
+
+    out_file.flush()
 }
@@ -34,6 +34,11 @@ static S_BOX: [[u8; 16]; 16] = [
      0x91, 0x14, 0x10, 0xEA, 0x77, 0x6C, 0xDA, 0x1D,],
 ];
 
+/// The S-box as a table of 256 outputs
+pub(super) fn s_box() -> &'static [u8] {
+    S_BOX.as_flattened()
+}
+
 pub(super) fn subs_byte(byte: u8) -> u8 {
     let row = (byte >> 4) as usize;
     let col = (byte & 0xF) as usize;
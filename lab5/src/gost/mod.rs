@@ -5,29 +5,29 @@ use crate::MyHasher;
 
 use lab1::*;
 
-fn step_hash_function(h_in: V256, m: V256) -> V256 {
-    fn key_gen(h_in: V256, m: V256) -> [V256; 4] {
+fn step_hash_function(h_in: V256, m: V256, params: &Params) -> V256 {
+    fn key_gen(h_in: V256, m: V256) -> [MagmaKey; 4] {
         fn transform_a(y: V256) -> V256 {
-            let [y4, y3, y2, y1] = y.into();
-            [y1 ^ y2, y4, y3, y2].into()
+            let [y1, y2, y3, y4]: [u64; 4] = y.into();
+            [y2, y3, y4, y1 ^ y2].into()
         }
 
         // Precomputed coefficients
         #[rustfmt::skip]
         fn transform_p(v: V256) -> V256 {
-            let [y32, y31, y30, y29, y28, y27, y26, y25, y24, y23, y22, y21,
-                 y20, y19, y18, y17, y16, y15, y14, y13, y12, y11, y10, y9,
-                 y8, y7, y6, y5, y4, y3, y2, y1] = v.into();
+            let [y1, y2, y3, y4, y5, y6, y7, y8, y9, y10, y11, y12,
+                 y13, y14, y15, y16, y17, y18, y19, y20, y21, y22, y23, y24,
+                 y25, y26, y27, y28, y29, y30, y31, y32]: [u8; 32] = v.into();
 
-            [y32, y24, y16, y8, y31, y23, y15, y7, y30, y22,
-             y14, y6, y29, y21, y13, y5, y28,y20, y12, y4, y27,
-             y19, y11, y3, y26, y18, y10, y2, y25, y17, y9, y1,].into()
+            [y1, y9, y17, y25, y2, y10, y18, y26, y3, y11,
+             y19, y27, y4, y12, y20, y28, y5, y13, y21, y29, y6,
+             y14, y22, y30, y7, y15, y23, y31, y8, y16, y24, y32,].into()
         }
 
         // Other constants are zeroes
         const C3: V256 = V256::new(
-            0xff00ffff000000ffff0000ff00ffff00,
             0x00ff00ff00ff00ffff00ff00ff00ff00,
+            0xff00ffff000000ffff0000ff00ffff00,
         );
 
         let mut u = h_in;
@@ -51,7 +51,7 @@ fn step_hash_function(h_in: V256, m: V256) -> V256 {
         let k3 = next_key();
         let k4 = next_key();
 
-        [k1, k2, k3, k4]
+        [k1, k2, k3, k4].map(|k| MagmaKey::new(k.into()))
     }
 
     // Key generation
@@ -60,9 +60,9 @@ fn step_hash_function(h_in: V256, m: V256) -> V256 {
     // Encrypting transform
     let h: [u64; 4] = h_in.into();
 
-    let s: V256 = [3, 2, 1, 0]
-        .map(|i| simple_swap(h[i], keys[i].into()))
-        .into();
+    let mut s = h;
+    simple_swap_keys_with(&mut s, &keys.each_ref(), params);
+    let s: V256 = s.into();
 
     // Mixing transform
 
@@ -93,6 +93,7 @@ pub struct GostContext {
     h: V256,
     sum: V256,
     len: usize,
+    params: Params,
 }
 
 impl GostContext {
@@ -103,8 +104,22 @@ impl GostContext {
         }
     }
 
+    pub fn with_sbox(h: V256, sbox: SBoxSet) -> Self {
+        Self {
+            h,
+            params: Params::new(sbox),
+            ..Default::default()
+        }
+    }
+
+    /// Chooses how the rounds of the cipher are computed, the hash is the same
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.params.backend = backend;
+        self
+    }
+
     fn internal_iter(&mut self, m: V256) {
-        self.h = step_hash_function(self.h, m);
+        self.h = step_hash_function(self.h, m, &self.params);
         self.len += 256;
         self.sum += m;
     }
@@ -112,17 +127,20 @@ impl GostContext {
     fn finalize(mut self, m: &[u8]) -> V256 {
         self.len += m.len() * u8::BITS as usize;
 
-        let mut m_buff = [0; 32];
+        // The last partial block is padded with zeroes in the high bytes
+        if !m.is_empty() {
+            let mut m_buff = [0; 32];
 
-        m_buff[32 - m.len()..].copy_from_slice(m);
+            m_buff[..m.len()].copy_from_slice(m);
 
-        let m = V256::from(m_buff);
+            let m = V256::from(m_buff);
 
-        // something something
-        self.sum += m;
-        self.h = step_hash_function(self.h, m);
-        self.h = step_hash_function(self.h, V256::new(self.len as u128, 0));
-        self.h = step_hash_function(self.h, self.sum);
+            self.sum += m;
+            self.h = step_hash_function(self.h, m, &self.params);
+        }
+
+        self.h = step_hash_function(self.h, V256::new(self.len as u128, 0), &self.params);
+        self.h = step_hash_function(self.h, self.sum, &self.params);
         self.h
     }
 }
@@ -142,3 +160,79 @@ impl MyHasher for GostContext {
         self.finalize(remainder).into()
     }
 }
+
+/// Formats the hash as a byte string, the way the standard's test vectors are written
+pub fn format_hash(h: [u64; 4]) -> String {
+    h.iter()
+        .flat_map(|w| w.to_le_bytes())
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_gost(value: &[u8], expected_hash: &str) {
+        let h = GostContext::new(Default::default()).process_to_end(value);
+        assert_eq!(format_hash(h), expected_hash)
+    }
+
+    #[test]
+    fn empty() {
+        assert_gost(
+            b"",
+            "ce85b99cc46752fffee35cab9a7b0278abb4c2d2055cff685af4912c49490f8d",
+        );
+    }
+
+    #[test]
+    fn abc() {
+        assert_gost(
+            b"abc",
+            "f3134348c44fb1b2a277729e2285ebb5cb5e0f29c975bc753b70497c06a4d51d",
+        );
+    }
+
+    #[test]
+    fn full_block() {
+        assert_gost(
+            b"This is message, length=32 bytes",
+            "b1c466d37519b82e8319819ff32595e047a28cb6f83eff1c6916a815a637fffa",
+        );
+    }
+
+    #[test]
+    fn crypto_pro_param_set() {
+        let h = GostContext::with_sbox(Default::default(), SBoxSet::GOST_3411_CRYPTO_PRO)
+            .process_to_end(b"abc");
+
+        assert_eq!(
+            format_hash(h),
+            "b285056dbf18d7392d7677369524dd14747459ed8143997e163b2986f92fd42c"
+        );
+    }
+
+    #[test]
+    fn bitsliced_same_hash() {
+        let message: Vec<u8> = (0..200u8).collect();
+
+        for sbox in [SBoxSet::GOST_3411_TEST, SBoxSet::GOST_3411_CRYPTO_PRO] {
+            let hash = |backend| {
+                GostContext::with_sbox(Default::default(), sbox)
+                    .with_backend(backend)
+                    .process_to_end(&message)
+            };
+
+            assert_eq!(hash(Backend::Bitsliced), hash(Backend::Table));
+        }
+    }
+
+    #[test]
+    fn long() {
+        assert_gost(
+            b"Suppose the original message has length = 50 bytes",
+            "471aba57a60a770d3a76130635c1fbea4ef14de51f78b4ae57dd893b62f55208",
+        );
+    }
+}
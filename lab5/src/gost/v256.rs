@@ -30,14 +30,14 @@ macro_rules! transmute_impl {
         impl From<$type> for V256 {
             fn from(value: $type) -> Self {
                 use std::mem::transmute;
-                unsafe { Self(transmute(value)) }
+                unsafe { Self(transmute::<$type, [u128; 2]>(value)) }
             }
         }
 
         impl From<V256> for $type {
             fn from(value: V256) -> Self {
                 use std::mem::transmute;
-                unsafe { transmute(value.0) }
+                unsafe { transmute::<[u128; 2], $type>(value.0) }
             }
         }
     };
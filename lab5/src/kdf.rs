@@ -0,0 +1,104 @@
+//! Keys from passphrases: PBKDF2 of RFC 8018 with HMAC on GOST R 34.11-94
+
+use lab1::{Backend, SBoxSet};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{gost::GostContext, my_hasher::MyHasher};
+
+pub const KDF_ITERATIONS: u32 = 10_000;
+
+/// Block size of GOST R 34.11-94, the size of the HMAC key block
+const BLOCK_SIZE: usize = 32;
+
+/// The passphrase keys the cipher of the hash, so the rounds run in constant time
+fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    let h = GostContext::with_sbox(Default::default(), SBoxSet::GOST_3411_CRYPTO_PRO)
+        .with_backend(Backend::Bitsliced)
+        .process_to_end(&parts.concat());
+
+    let mut bytes = [0; 32];
+    bytes
+        .chunks_exact_mut(8)
+        .zip(h)
+        .for_each(|(bytes, word)| bytes.copy_from_slice(&word.to_le_bytes()));
+    bytes
+}
+
+/// HMAC of RFC 2104 on GOST R 34.11-94 with the CryptoPro parameters
+pub fn hmac_gost(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0; BLOCK_SIZE];
+
+    if key.len() > BLOCK_SIZE {
+        block = hash(&[key]);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let inner = hash(&[&block.map(|b| b ^ 0x36), message]);
+    hash(&[&block.map(|b| b ^ 0x5c), &inner])
+}
+
+/// PBKDF2 with `hmac_gost`, the 256-bit key is a single block of it
+pub fn derive_key(passphrase: &[u8], salt: &[u8], iterations: u32) -> Zeroizing<[u8; 32]> {
+    let mut u = hmac_gost(passphrase, &[salt, &1u32.to_be_bytes()].concat());
+    let mut key = Zeroizing::new(u);
+
+    for _ in 1..iterations {
+        u = hmac_gost(passphrase, &u);
+        key.iter_mut().zip(u).for_each(|(k, u)| *k ^= u);
+    }
+
+    u.zeroize();
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_key_is_hashed() {
+        let key = [7; 40];
+        assert_eq!(hmac_gost(&key, b"abc"), hmac_gost(&hash(&[&key]), b"abc"));
+        assert_ne!(hmac_gost(&key, b"abc"), hmac_gost(&key[..32], b"abc"));
+    }
+
+    #[test]
+    fn iterations_chain() {
+        let salt = [1; 16];
+        let u1 = hmac_gost(b"password", &[&salt[..], &[0, 0, 0, 1]].concat());
+        let u2 = hmac_gost(b"password", &u1);
+
+        assert_eq!(*derive_key(b"password", &salt, 1), u1);
+
+        let expected: Vec<u8> = u1.iter().zip(u2).map(|(a, b)| a ^ b).collect();
+        assert_eq!(derive_key(b"password", &salt, 2).to_vec(), expected);
+    }
+
+    #[test]
+    fn known_answer() {
+        // The PBKDF2-HMAC-GOSTR3411-94 vectors of the CryptoPro parameters
+        let hex = |key: [u8; 32]| key.iter().map(|b| format!("{b:02x}")).collect::<String>();
+
+        assert_eq!(
+            hex(*derive_key(b"password", b"salt", 1)),
+            "7314e7c04fb2e662c543674253f68bd0b73445d07f241bed872882da21662d58"
+        );
+        assert_eq!(
+            hex(*derive_key(b"password", b"salt", 2)),
+            "990dfa2bd965639ba48b07b792775df79f2db34fef25f274378872fed7ed1bb3"
+        );
+        assert_eq!(
+            hex(*derive_key(b"password", b"salt", 4096)),
+            "1f1829a94bdff5be10d0aeb36af498e7a97467f3b31116a5a7c1afff9deadafe"
+        );
+    }
+
+    #[test]
+    fn salt_matters() {
+        assert_ne!(
+            derive_key(b"password", &[1; 16], 3),
+            derive_key(b"password", &[2; 16], 3)
+        );
+    }
+}
@@ -3,9 +3,16 @@
 use std::fs::File;
 
 mod gost;
+mod kdf;
 mod my_hasher;
+#[cfg(feature = "rpassword")]
+mod passphrase;
 mod sha1;
 
+pub use kdf::*;
+#[cfg(feature = "rpassword")]
+pub use passphrase::*;
+
 use anyhow::Result;
 use my_hasher::MyHasher;
 use std::io::Read;
@@ -6,7 +6,7 @@ use std::path::PathBuf;
 use std::{fs::File, io::*};
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{builder::PossibleValuesParser, builder::TypedValueParser, Parser};
 
 use my_hasher::MyHasher;
 
@@ -19,6 +19,18 @@ struct Args {
 
     #[arg(short, long, help = "Use gost insead of sha1")]
     gost: bool,
+
+    #[arg(long, value_name = "NAME", help = "S-box parameter set for gost")]
+    sbox: Option<lab1::SBoxSet>,
+
+    #[arg(
+        long,
+        default_value_t,
+        value_parser = PossibleValuesParser::new(lab1::Backend::ALL.map(lab1::Backend::name))
+            .map(|name| name.parse::<lab1::Backend>().unwrap()),
+        help = "How the gost cipher rounds are computed, table is faster but not constant time"
+    )]
+    backend: lab1::Backend,
 }
 
 fn compute_hash<H, O>(mut input: File, mut hash_context: H) -> Result<O>
@@ -50,8 +62,13 @@ fn main() -> Result<()> {
     let input = File::open(args.input).context("Couldn't open input file")?;
 
     if args.gost {
-        let hash = compute_hash(input, gost::GostContext::new(Default::default()))?;
-        println!("{}", sha1::format_big_hex(hash));
+        let context = match args.sbox {
+            Some(sbox) => gost::GostContext::with_sbox(Default::default(), sbox),
+            None => gost::GostContext::new(Default::default()),
+        }
+        .with_backend(args.backend);
+        let hash = compute_hash(input, context)?;
+        println!("{}", gost::format_hash(hash));
     } else {
         let hash = compute_hash(input, Sha1Context::new())?;
         println!("{}", sha1::format_big_hex(hash));
@@ -16,6 +16,7 @@ pub trait MyHasher: Sized {
         remainder
     }
 
+    #[allow(unused)]
     fn process_to_end(mut self, message: &[u8]) -> Self::Output {
         let remainder = self.process_chunks(message);
         self.finish(remainder)
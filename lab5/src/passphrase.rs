@@ -0,0 +1,46 @@
+//! The passphrase a key is derived from, see `derive_key`
+
+use std::io;
+
+use zeroize::Zeroizing;
+
+/// Environment variable the passphrase is taken from before asking for it
+pub const PASSPHRASE_VAR: &str = "LAB_PASSPHRASE";
+
+/// Takes the passphrase from `PASSPHRASE_VAR` or asks for it on the terminal,
+/// twice when `confirm` is set
+pub fn read_passphrase(confirm: bool) -> io::Result<Zeroizing<String>> {
+    let passphrase = match std::env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) => Zeroizing::new(passphrase),
+        Err(_) => {
+            let passphrase = rpassword::prompt_password("Passphrase: ").map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("no key file and no passphrase in {PASSPHRASE_VAR}: {e}"),
+                )
+            })?;
+            let passphrase = Zeroizing::new(passphrase);
+
+            if confirm
+                && *Zeroizing::new(rpassword::prompt_password("Repeat the passphrase: ")?)
+                    != *passphrase
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "passphrases don't match",
+                ));
+            }
+
+            passphrase
+        }
+    };
+
+    if passphrase.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the passphrase is empty",
+        ));
+    }
+
+    Ok(passphrase)
+}
@@ -19,9 +19,9 @@ const H_INIT: [Wrapping<u32>; 5] = [
 type AuxFn = fn(Wrapping<u32>, Wrapping<u32>, Wrapping<u32>) -> Wrapping<u32>;
 
 const AUX_FUNC_TABLE: [AuxFn; 4] = {
-    let ch = |x: Wrapping<u32>, y, z| (((x) & (y)) | ((!x) & (z)));
-    let parity = |x, y, z| ((x) ^ (y) ^ (z));
-    let maj = |x, y, z| (((x) & (y)) | ((x) & (z)) | ((y) & (z)));
+    let ch = |x: Wrapping<u32>, y, z| ((x) & (y)) | ((!x) & (z));
+    let parity = |x, y, z| (x) ^ (y) ^ (z);
+    let maj = |x, y, z| ((x) & (y)) | ((x) & (z)) | ((y) & (z));
 
     [ch, parity, maj, parity]
 };